@@ -0,0 +1,39 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+@group(2) @binding(0) var<uniform> color: vec4<f32>;
+@group(2) @binding(1) var<uniform> outline_color: vec4<f32>;
+// Width of the outline as a fraction of the sprite size. The mesh is padded by
+// this much on each side, so the outline has room outside the sprite.
+@group(2) @binding(2) var<uniform> thickness: f32;
+@group(2) @binding(3) var texture: texture_2d<f32>;
+@group(2) @binding(4) var texture_sampler: sampler;
+
+fn alpha_at(uv: vec2<f32>) -> f32 {
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) {
+        return 0.0;
+    }
+    return textureSampleLevel(texture, texture_sampler, uv, 0.0).a;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Undo the padding, so the sprite covers 0..1 with the margin around it
+    let t = thickness;
+    let uv = mesh.uv * (1.0 + 2.0 * t) - vec2(t);
+    if alpha_at(uv) > 0.0 {
+        return color * textureSampleLevel(texture, texture_sampler, uv, 0.0);
+    }
+
+    // Transparent pixels with an opaque neighbour `thickness` away form the
+    // outline, diagonals included so corners are filled in
+    var neighbours = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            neighbours = max(neighbours, alpha_at(uv + vec2(f32(x), f32(y)) * t));
+        }
+    }
+    if neighbours > 0.0 {
+        return outline_color;
+    }
+    return vec4(0.0);
+}
@@ -1,272 +1,8401 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
 use bevy::{
-    color::palettes::css::WHITE, math::VectorSpace, prelude::*, sprite::MaterialMesh2dBundle,
+    app::ScheduleRunnerPlugin,
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
+    audio::Volume,
+    color::palettes::css::{GOLD, WHITE},
+    diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    ecs::entity::{Entities, EntityHashMap},
+    prelude::*,
+    render::{
+        camera::{RenderTarget, ScalingMode},
+        mesh::VertexAttributeValues,
+        primitives::Aabb,
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            AsBindGroup, Extent3d, ShaderRef, TextureDimension, TextureFormat, TextureUsages,
+        },
+        settings::{RenderCreation, WgpuSettings},
+        texture::ImageSampler,
+        view::RenderLayers,
+        RenderPlugin,
+    },
+    scene::{ron, serde::SceneDeserializer},
+    sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle, Mesh2dHandle},
+    time::{Real, TimeUpdateStrategy},
+    window::{ExitCondition, PrimaryWindow, WindowResized},
+    winit::WinitPlugin,
 };
 use bevy_rapier2d::prelude::*;
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(10.0))
-        // .add_plugins(RapierDebugRenderPlugin::default())
-        .add_systems(Startup, setup)
-        .add_systems(FixedUpdate, (apply_kinematics/* , update_camera*/))
-        .add_systems(Update, (animate_sprites, get_player_input))
-        .run();
+    let benchmark = Benchmark::from_env();
+    let mut app = App::new();
+    if benchmark.enabled {
+        // Headless: no window, renderer or event loop, so it runs anywhere and
+        // only the game's own work is timed. Frames step time by a fixed
+        // BENCHMARK_STEP, so every run simulates the same thing.
+        app.add_plugins(
+            DefaultPlugins
+                .set(ImagePlugin::default_nearest())
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: RenderCreation::Automatic(WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+        )
+        .add_plugins(ScheduleRunnerPlugin::run_loop(Duration::ZERO))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(BENCHMARK_STEP));
+    } else {
+        app.add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()));
+    }
+    app.add_plugins(
+        RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(WORLD_SCALE.pixels_per_meter())
+            .in_fixed_schedule(),
+    )
+    // .add_plugins(RapierDebugRenderPlugin::default())
+    .add_plugins(Material2dPlugin::<OutlineMaterial>::default())
+    .add_plugins(FrameTimeDiagnosticsPlugin)
+    .init_asset::<AnimationLayout>()
+    .init_asset_loader::<AnimationLayoutLoader>()
+    .register_type::<PlayerTag>()
+    .register_type::<ColliderTag>()
+    .register_type::<MainCameraTag>()
+    .register_type::<CameraTarget>()
+    .register_type::<FramePair>()
+    .register_type::<Frozen>()
+    .register_type::<Noclip>()
+    .register_type::<HintText>()
+    .register_type::<Tooltip>()
+    .register_type::<TooltipText>()
+    .register_type::<FaceDirection>()
+    .register_type::<Faction>()
+    .register_type::<MoveSettings>()
+    .register_type::<CameraValues>()
+    .register_type::<ScreenShake>()
+    .register_type::<Velocity>()
+    .register_type::<AnimationInd>()
+    .register_type::<AnimationTimer>()
+    .register_type::<AnimationEvents>()
+    .register_type::<Boredom>()
+    .register_type::<WalkTransition>()
+    .register_type::<AnimationWatchdog>()
+    .register_type::<Interactable>()
+    .register_type::<Sign>()
+    .register_type::<Dialogue>()
+    .register_type::<Weather>()
+    .register_type::<LevelIntro>()
+    .register_type::<WorldFlags>()
+    .register_type::<IntroPan>()
+    .register_type::<WeatherParticle>()
+    .register_type::<Highlighted>()
+    .register_type::<LevelEntity>()
+    .register_type::<LevelBox>()
+    .register_type::<LevelCrate>()
+    .register_type::<WaterZone>()
+    .register_type::<TallGrass>()
+    .register_type::<FadeWhenOccluding>()
+    .register_type::<OneWayLedge>()
+    .register_type::<TerrainEffects>()
+    .register_type::<Ripple>()
+    .register_type::<Rustle>()
+    .register_type::<BenchmarkEnemy>()
+    .register_type::<Lifetime>()
+    .register_type::<Tween>()
+    .register_type::<BusVolume>()
+    .register_type::<AntiStuck>()
+    .register_type::<SquashStretch>()
+    .register_type::<FogOfWar>()
+    .register_type::<Light2d>()
+    .register_type::<ParallaxLayer>()
+    .register_type::<ColliderFromSprite>()
+    .insert_resource(TimeScale(1.0))
+    .init_resource::<HintQueue>()
+    .init_resource::<LastInputDevice>()
+    .insert_resource(Weather {
+        kind: WeatherKind::Rain,
+        intensity: 0.3,
+        wind: 0.1,
+    })
+    .insert_resource(LevelIntro {
+        waypoints: Vec::new(),
+        per_point_time: 1.5,
+    })
+    .insert_resource(DialogueSettings {
+        chars_per_sec: 40.0,
+    })
+    .init_resource::<InputContexts>()
+    .init_resource::<WorldFlags>()
+    .init_resource::<SpriteColliders>()
+    .insert_resource(SaveSlots { selected: 1 })
+    .insert_resource(NavRepeat {
+        initial_delay: 0.4,
+        repeat_rate: 8.0,
+    })
+    .init_resource::<Playtime>()
+    .insert_resource(AutoSave {
+        interval: Some(Timer::from_seconds(120.0, TimerMode::Repeating)),
+        dir: SAVE_DIR.to_string(),
+    })
+    .insert_resource(InputSettings {
+        stick_dead_zone: 0.2,
+    })
+    .insert_resource(PlayerCollider::from_env())
+    .insert_resource(FogSettings {
+        extent: Vec2::new(1600.0, 1200.0),
+        cell_size: WORLD_SCALE.texels(4.0),
+        vision_radius: 220.0,
+        explored_alpha: 0.6,
+    })
+    .insert_resource(LightingSettings {
+        darkness: 0.45,
+        cell_size: WORLD_SCALE.texels(4.0),
+        max_lights: 16,
+    })
+    .insert_resource(FrameLog {
+        enabled: false,
+        interval: Timer::from_seconds(5.0, TimerMode::Repeating),
+        csv_path: None,
+        frame_ms: Vec::new(),
+    })
+    .insert_resource(benchmark)
+    .insert_resource(DynamicResolution {
+        target_frame_ms: 1000.0 / 60.0,
+        min_scale: 0.5,
+        max_scale: 1.0,
+        scale: 1.0,
+    })
+    .insert_resource(UiScaleSettings {
+        reference_size: Vec2::new(1280.0, 720.0),
+        min_scale: 0.75,
+        max_scale: 2.0,
+    })
+    .insert_resource(OutlineSettings {
+        color: Color::from(GOLD),
+        thickness: 0.06,
+    })
+    .insert_resource(OcclusionFadeSettings {
+        faded_alpha: 0.35,
+        rate: 8.0,
+    })
+    .insert_resource(EntityBudget {
+        enabled: cfg!(debug_assertions),
+        limits: HashMap::from([
+            (TypeId::of::<Ripple>(), 32),
+            (TypeId::of::<Rustle>(), 48),
+            (TypeId::of::<LevelEntity>(), 1000),
+        ]),
+    })
+    .insert_resource(FactionTints(HashMap::from([
+        (Faction::Player, Color::srgb(0.75, 0.85, 1.0)),
+        (Faction::Enemy, Color::srgb(1.0, 0.7, 0.7)),
+    ])))
+    .insert_resource(MoveQuantization {
+        enabled: false,
+        step: 1.0 / 256.0,
+    })
+    .insert_resource(ImpactShakeSettings {
+        min_force: 2000.0,
+        pixels_per_force: 0.0004,
+    })
+    .insert_resource(DebugFlags {
+        keys_enabled: cfg!(debug_assertions),
+        controllers: false,
+        velocities: false,
+        interact_ranges: false,
+    })
+    .insert_resource(AudioVolumes {
+        master: 1.0,
+        music: 1.0,
+        sfx: 1.0,
+        ui: 1.0,
+    })
+    .insert_resource(SpatialAudioSettings {
+        max_range: 600.0,
+        falloff: 2.0,
+    })
+    .add_event::<SfxEvent>()
+    .add_event::<SpatialSfxEvent>()
+    .add_event::<ShakeEvent>()
+    .add_event::<AnimFrameEvent>()
+    .add_event::<TweenCompleted>()
+    .add_event::<TeleportEvent>()
+    .init_state::<GameState>()
+    .add_systems(Startup, (load_assets, disable_gravity))
+    .add_systems(
+        Update,
+        check_assets_loaded.run_if(in_state(GameState::Loading)),
+    )
+    .add_systems(OnExit(GameState::Loading), despawn_loading_screen)
+    .add_systems(OnEnter(GameState::Playing), (setup, start_music))
+    .add_systems(
+        OnEnter(GameState::Playing),
+        spawn_benchmark_load.run_if(|benchmark: Res<Benchmark>| benchmark.enabled),
+    )
+    .add_systems(First, apply_time_scale)
+    .add_systems(Update, scale_ui_to_window)
+    .add_systems(Update, (adjust_render_scale, resize_scene_target).chain())
+    .add_systems(
+        FixedUpdate,
+        (
+            update_terrain_effects,
+            get_player_input,
+            squash_and_stretch,
+            apply_kinematics,
+            pass_one_way_ledges,
+            unstick_controllers,
+        )
+            .chain()
+            .before(PhysicsSet::SyncBackend)
+            .run_if(in_state(GameState::Playing)),
+    )
+    .add_systems(
+        FixedUpdate,
+        (
+            shake_on_impacts,
+            quantize_positions.run_if(|quantization: Res<MoveQuantization>| quantization.enabled),
+        )
+            .after(PhysicsSet::Writeback)
+            .run_if(in_state(GameState::Playing)),
+    )
+    .add_systems(
+        FixedUpdate,
+        turn_benchmark_enemies.after(PhysicsSet::Writeback).run_if(
+            in_state(GameState::Playing).and_then(|benchmark: Res<Benchmark>| benchmark.enabled),
+        ),
+    )
+    .add_systems(
+        Update,
+        (
+            reload_animation_layouts,
+            animate_sprites,
+            frame_event_sfx,
+            apply_faction_tints,
+            (
+                apply_teleports,
+                start_level_intro,
+                run_level_intro,
+                update_camera,
+                bob_camera,
+                shake_camera,
+                scroll_parallax,
+            )
+                .chain(),
+            (highlight_interactables, fade_occluders),
+            reveal_fog,
+            update_lightmap,
+            spawn_level_boxes,
+            spawn_ledges,
+            spawn_water_zones,
+            spawn_crates,
+            spawn_torches,
+            generate_sprite_colliders,
+            count_playtime,
+            level_scene_keys,
+            auto_save,
+            spawn_tall_grass,
+            (
+                spawn_ripples,
+                spawn_rustles,
+                despawn_expired,
+                animate_ripples,
+                animate_rustles,
+                run_tweens,
+            )
+                .chain(),
+            (play_sfx, apply_bus_volumes::<AudioSink>).chain(),
+        )
+            .run_if(in_state(GameState::Playing)),
+    )
+    // HUD and UI
+    .add_systems(
+        Update,
+        (
+            (track_input_device, contextual_hints, update_hint_text).chain(),
+            navigate_save_slots,
+            show_tooltips,
+            (read_signs, advance_dialogue).chain(),
+            (spawn_weather, move_weather).chain(),
+        )
+            .run_if(in_state(GameState::Playing)),
+    )
+    // Debug drawing
+    .add_systems(
+        Update,
+        (
+            (
+                toggle_debug_flags,
+                toggle_noclip,
+                debug_spawn_at_cursor,
+                debug_teleport_to_cursor,
+                debug_frame_pair,
+            )
+                .run_if(debug_flag(|flags| flags.keys_enabled)),
+            draw_controller_gizmos.run_if(debug_flag(|flags| flags.controllers)),
+            draw_velocity_gizmos.run_if(debug_flag(|flags| flags.velocities)),
+            draw_interact_range_gizmos.run_if(debug_flag(|flags| flags.interact_ranges)),
+        )
+            .run_if(in_state(GameState::Playing)),
+    )
+    .add_systems(
+        Last,
+        (
+            check_entity_budget::<Ripple>,
+            check_entity_budget::<Rustle>,
+            check_entity_budget::<LevelEntity>,
+        )
+            .run_if(|budget: Res<EntityBudget>| budget.enabled),
+    )
+    .add_systems(
+        Last,
+        log_frame_pacing.run_if(|log: Res<FrameLog>| log.enabled),
+    )
+    .add_systems(
+        Last,
+        run_benchmark.run_if(
+            in_state(GameState::Playing).and_then(|benchmark: Res<Benchmark>| benchmark.enabled),
+        ),
+    )
+    .run();
+}
+
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+enum GameState {
+    #[default]
+    Loading,
+    Playing,
 }
 
 // Tags
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct PlayerTag;
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct ColliderTag;
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct MainCameraTag;
 
+// Window-sized sprite showing what the main camera rendered, drawn by the
+// presenting camera on PRESENT_LAYER
+#[derive(Component)]
+struct ScenePresenter;
+
+// Stops the entity's movement, input and animation where they are, without
+// touching its velocity or frame so it carries on when unfrozen
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Frozen;
+
+// Moves straight through walls, with the collider disabled and velocity applied
+// to the transform instead of going through the character controller
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Noclip;
+
+trait FreezeCommands {
+    fn freeze(&mut self, entity: Entity);
+    fn unfreeze(&mut self, entity: Entity);
+}
+
+impl FreezeCommands for Commands<'_, '_> {
+    fn freeze(&mut self, entity: Entity) {
+        self.entity(entity).insert(Frozen);
+    }
+
+    fn unfreeze(&mut self, entity: Entity) {
+        self.entity(entity).remove::<Frozen>();
+    }
+}
+
+// Stand-in camera target the level intro moves between its waypoints
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct IntroPan {
+    // Index into the waypoints, one past the end is the player
+    point: usize,
+    timer: Timer,
+}
+
+// Followed by the main camera, the player has it unless something else should be framed
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct CameraTarget;
+
+// Put on the main camera to frame two entities instead of following
+// CameraTarget, like the player and a boss. The camera centres between them
+// and zooms out to fit both.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct FramePair {
+    a: Entity,
+    b: Entity,
+    // Pixels kept clear around both, at normal zoom
+    padding: f32,
+    // Furthest the camera zooms out, 2 shows twice as much
+    max_zoom: f32,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct HintText;
+
+// UI text following the cursor, shows the Tooltip of the hovered entity
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct TooltipText;
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingText;
+
 // Other structs/enums
-#[derive(Debug)]
+#[derive(Debug, Reflect)]
 enum FacingDirection {
-    LEFT,
-    RIGHT,
-    UP,
-    DOWN,
+    Left,
+    Right,
+    Up,
+    Down,
 }
 
+impl FacingDirection {
+    fn vector(&self) -> Vec2 {
+        match self {
+            FacingDirection::Left => Vec2::NEG_X,
+            FacingDirection::Right => Vec2::X,
+            FacingDirection::Up => Vec2::Y,
+            FacingDirection::Down => Vec2::NEG_Y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect, Deserialize)]
+enum AnimClip {
+    Walk,
+    Idle,
+    Bored,
+    StartWalk,
+    StopWalk,
+}
+
+impl AnimClip {
+    // Played once and handed over to walk or idle, rather than looped
+    fn is_one_shot(self) -> bool {
+        matches!(
+            self,
+            AnimClip::Bored | AnimClip::StartWalk | AnimClip::StopWalk
+        )
+    }
+}
+
+// Sends an AnimFrameEvent with `tag` whenever `clip` reaches `frame`
+#[derive(Reflect, Deserialize, Clone)]
+struct FrameTag {
+    clip: AnimClip,
+    frame: usize,
+    tag: String,
+}
+
+#[derive(Reflect, Deserialize, Clone)]
 struct AnimIndices {
     left: usize,
     right: usize,
     up: usize,
     down: usize,
+    frames: usize,
+    frame_time: f32,
+    // Seconds a one-shot clip may play before it's forced to hand over,
+    // twice its length when not set
+    #[serde(default)]
+    max_state_time: Option<f32>,
+}
+
+impl AnimIndices {
+    fn max_state_time(&self) -> f32 {
+        self.max_state_time
+            .unwrap_or(self.frames as f32 * self.frame_time * 2.0)
+    }
+
+    fn start(&self, facing: &FacingDirection) -> usize {
+        match facing {
+            FacingDirection::Left => self.left,
+            FacingDirection::Right => self.right,
+            FacingDirection::Up => self.up,
+            FacingDirection::Down => self.down,
+        }
+    }
+}
+
+/// Spritesheet grid and animation clips of a character, from a `.anim.ron`
+/// file. Edits are picked up while running when Bevy's file watcher is on.
+#[derive(Asset, TypePath, Deserialize)]
+struct AnimationLayout {
+    columns: u32,
+    rows: u32,
+    walk: AnimIndices,
+    idle: AnimIndices,
+    // Played once after standing idle for a while, see Boredom
+    #[serde(default)]
+    bored: Option<AnimIndices>,
+    // Played once between idle and walk, see WalkTransition
+    #[serde(default)]
+    start_walk: Option<AnimIndices>,
+    #[serde(default)]
+    stop_walk: Option<AnimIndices>,
+    #[serde(default)]
+    events: Vec<FrameTag>,
+}
+
+impl AnimationLayout {
+    fn clip(&self, clip: AnimClip) -> Option<&AnimIndices> {
+        match clip {
+            AnimClip::Walk => Some(&self.walk),
+            AnimClip::Idle => Some(&self.idle),
+            AnimClip::Bored => self.bored.as_ref(),
+            AnimClip::StartWalk => self.start_walk.as_ref(),
+            AnimClip::StopWalk => self.stop_walk.as_ref(),
+        }
+    }
+
+    /// Logs an error for every tag on a frame its clip doesn't have
+    fn events(&self) -> AnimationEvents {
+        for tag in &self.events {
+            let frames = self.clip(tag.clip).map_or(0, |clip| clip.frames);
+            if tag.frame >= frames {
+                error!(
+                    "\"{}\" is tagged on frame {} of {:?}, which has {frames} frames",
+                    tag.tag, tag.frame, tag.clip
+                );
+            }
+        }
+        AnimationEvents(self.events.clone())
+    }
+
+    /// Logs an error for every clip that runs past the end of the grid
+    fn indices(&self) -> AnimationInd {
+        let frame_count = (self.columns * self.rows) as usize;
+        let clips = [
+            ("walk", Some(&self.walk)),
+            ("idle", Some(&self.idle)),
+            ("bored", self.bored.as_ref()),
+            ("start_walk", self.start_walk.as_ref()),
+            ("stop_walk", self.stop_walk.as_ref()),
+        ];
+        for (name, clip) in clips
+            .into_iter()
+            .filter_map(|(name, clip)| Some((name, clip?)))
+        {
+            for start in [clip.left, clip.right, clip.up, clip.down] {
+                if start + clip.frames > frame_count {
+                    error!(
+                        "{name} frames {start}..{} are outside the {frame_count} frame grid",
+                        start + clip.frames
+                    );
+                }
+            }
+        }
+        AnimationInd {
+            walk: self.walk.clone(),
+            idle: self.idle.clone(),
+            bored: self.bored.clone(),
+            start_walk: self.start_walk.clone(),
+            stop_walk: self.stop_walk.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct AnimationLayoutLoader;
+
+impl AssetLoader for AnimationLayoutLoader {
+    type Asset = AnimationLayout;
+    type Settings = ();
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<AnimationLayout, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["anim.ron"]
+    }
+}
+
+/// Physical feel of a crate. The game is top-down, so how far a crate slides is
+/// set by its linear damping, friction only applies against walls and other crates.
+///
+/// | Preset   | Friction | Restitution | Linear damping | Density |
+/// |----------|----------|-------------|----------------|---------|
+/// | Heavy    | 1.0      | 0.0         | 12.0           | 4.0     |
+/// | Slippery | 0.0      | 0.1         | 0.5            | 1.0     |
+/// | Bouncy   | 0.3      | 0.9         | 2.0            | 0.8     |
+#[derive(Debug, Clone, Copy, Reflect)]
+enum CratePreset {
+    Heavy,
+    Slippery,
+    Bouncy,
+}
+
+impl CratePreset {
+    fn physics(self) -> (Friction, Restitution, Damping, ColliderMassProperties) {
+        let (friction, restitution, linear_damping, density) = match self {
+            CratePreset::Heavy => (1.0, 0.0, 12.0, 4.0),
+            CratePreset::Slippery => (0.0, 0.1, 0.5, 1.0),
+            CratePreset::Bouncy => (0.3, 0.9, 2.0, 0.8),
+        };
+
+        (
+            Friction::coefficient(friction),
+            Restitution::coefficient(restitution),
+            Damping {
+                linear_damping,
+                angular_damping: 1.0,
+            },
+            ColliderMassProperties::Density(density),
+        )
+    }
+
+    fn color(self) -> Color {
+        match self {
+            CratePreset::Heavy => Color::srgb(0.35, 0.22, 0.12),
+            CratePreset::Slippery => Color::srgb(0.6, 0.8, 0.9),
+            CratePreset::Bouncy => Color::srgb(0.9, 0.55, 0.2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum InputDevice {
+    #[default]
+    Keyboard,
+    Gamepad,
+}
+
+// Something the player can do, named in hints by its placeholder
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Move,
+    Peek,
+    PickSlot,
+    Save,
+    Load,
+    Interact,
+}
+
+impl Action {
+    const ALL: [Action; 6] = [
+        Action::Move,
+        Action::Peek,
+        Action::PickSlot,
+        Action::Save,
+        Action::Load,
+        Action::Interact,
+    ];
+
+    fn placeholder(self) -> &'static str {
+        match self {
+            Action::Move => "{move}",
+            Action::Peek => "{peek}",
+            Action::PickSlot => "{pick_slot}",
+            Action::Save => "{save}",
+            Action::Load => "{load}",
+            Action::Interact => "{interact}",
+        }
+    }
+
+    // How to do it on `device`, saving and loading are keyboard only
+    fn prompt(self, device: InputDevice) -> &'static str {
+        match (self, device) {
+            (Action::Move, InputDevice::Keyboard) => "WASD",
+            (Action::Move, InputDevice::Gamepad) => "Left stick",
+            (Action::Peek, InputDevice::Keyboard) => "Q",
+            (Action::Peek, InputDevice::Gamepad) => "Right stick",
+            (Action::PickSlot, InputDevice::Keyboard) => "F1-F3 or Up/Down",
+            (Action::PickSlot, InputDevice::Gamepad) => "D-pad up/down",
+            (Action::Save, _) => "F5",
+            (Action::Load, _) => "F9",
+            (Action::Interact, InputDevice::Keyboard) => "E",
+            (Action::Interact, InputDevice::Gamepad) => "South button",
+        }
+    }
+}
+
+// Replaces every Action placeholder in `text` with its prompt for `device`
+fn fill_prompts(text: &str, device: InputDevice) -> String {
+    Action::ALL.iter().fold(text.to_string(), |text, action| {
+        text.replace(action.placeholder(), action.prompt(device))
+    })
+}
+
+// What the player's input is currently driving
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputContext {
+    Gameplay,
+    // Nothing pushes it yet, it's for the menus to come
+    #[allow(dead_code)]
+    Menu,
+    Dialogue,
+    Cutscene,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum SfxKind {
+    Footstep,
+    Splash,
+    Rustle,
+    UiSelect,
+}
+
+impl SfxKind {
+    fn bus(self) -> AudioBus {
+        match self {
+            SfxKind::UiSelect => AudioBus::Ui,
+            SfxKind::Footstep | SfxKind::Splash | SfxKind::Rustle => AudioBus::Sfx,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+enum AudioBus {
+    Music,
+    Sfx,
+    Ui,
+}
+
+// Pair of opposing keys on one axis where the most recently pressed one wins.
+// Compares against the previous held state since just_pressed isn't reliable in FixedUpdate.
+#[derive(Default)]
+struct HeldAxis {
+    negative_held: bool,
+    positive_held: bool,
+    latest: f32,
+}
+
+impl HeldAxis {
+    fn update(&mut self, negative: bool, positive: bool) -> f32 {
+        if negative && !self.negative_held {
+            self.latest = -1.0;
+        }
+        if positive && !self.positive_held {
+            self.latest = 1.0;
+        }
+        self.negative_held = negative;
+        self.positive_held = positive;
+
+        match (negative, positive) {
+            (true, true) => self.latest,
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            (false, false) => 0.0,
+        }
+    }
+}
+
+// Events
+#[derive(Event)]
+struct SfxEvent {
+    kind: SfxKind,
+}
+
+// Sound made somewhere in the world, quieter the further it is from the camera
+#[derive(Event)]
+struct SpatialSfxEvent {
+    position: Vec2,
+    kind: SfxKind,
+}
+
+// Sent once each time an animation enters a frame tagged in its AnimationEvents
+#[derive(Event)]
+struct AnimFrameEvent {
+    entity: Entity,
+    tag: String,
+}
+
+#[derive(Event)]
+struct TweenCompleted {
+    // Nothing listens yet, it's for chaining effects off finished tweens
+    #[allow(dead_code)]
+    entity: Entity,
+}
+
+// Moves `entity` straight to `to`, the camera snaps there instead of easing
+// across if it's following it
+#[derive(Event)]
+struct TeleportEvent {
+    entity: Entity,
+    to: Vec2,
+}
+
+// Shakes the main camera by up to `intensity` pixels
+#[derive(Event)]
+struct ShakeEvent {
+    intensity: f32,
 }
 
 // Components
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct FaceDirection(FacingDirection);
 
-#[derive(Component)]
+// Team the entity's sprite is tinted for, see FactionTints
+#[derive(Component, Reflect, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+enum Faction {
+    Player,
+    Enemy,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct MoveSettings {
     is_walking: bool,
     speed: f32,
     accel: f32,
     fric: f32,
+    // Used instead of accel when input opposes the current velocity
+    turn_decel: f32,
+    // Off lets keyboard diagonals reach sqrt(2) * speed, so speed is no
+    // longer the top speed. The stick is never faster diagonally.
+    normalize_diagonal: bool,
+    // Without input, speeds below this (pixels per second) snap to zero
+    stop_speed: f32,
 }
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct CameraValues {
-    lerp_factor: f32,
+    // Per axis, so the camera can follow snappily sideways and lazily up and down
+    lerp_factor: Vec2,
+    // How the follow closes the gap, lerp_factor sets the pace for EaseInOut and Exponential
+    easing: CameraEasing,
+    // Carried between frames so springs keep their momentum
+    follow_velocity: Vec2,
+    // Set by a teleport of the camera target, jumps straight to it for one frame
+    snap: bool,
+    // Where the player sits relative to the screen centre, as a fraction of the viewport
+    screen_offset: Vec2,
+    // Furthest the camera can peek ahead, as a fraction of the viewport
+    peek_reach: f32,
+    // How quickly the peek eases out and back to centre, per second
+    peek_rate: f32,
+    peek_offset: Vec2,
+    // Radians around z the camera turns towards, 0 is upright
+    target_rotation: f32,
+    // How quickly the rotation eases towards the target, per second
+    rotation_rate: f32,
+    // How quickly the zoom eases in and out for FramePair, per second
+    zoom_rate: f32,
+    // Pixels the camera bobs at full walking speed
+    camera_bob: f32,
+    // Eased towards the player's fraction of full speed, 0 when idle
+    bob_strength: f32,
+    bob_offset: Vec2,
 }
 
-#[derive(Component)]
+#[derive(Debug, Clone, Copy, Reflect)]
+enum CameraEasing {
+    // Pixels per second, stops dead on arrival
+    Linear { speed: f32 },
+    // Critically damped spring, speeds up gently and settles without overshooting
+    EaseInOut,
+    // Fast at first, slowing as it closes in
+    Exponential,
+    // Bounces past the target when damping is below 2 * sqrt(stiffness)
+    Spring { stiffness: f32, damping: f32 },
+}
+
+// Applied on top of the follow position by shake_camera
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct ScreenShake {
+    // Pixels, stronger shakes are clamped to this
+    max_intensity: f32,
+    // Pixels per second the shake dies down by
+    decay: f32,
+    intensity: f32,
+    offset: Vec2,
+}
+
+// Pixels per second
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct Velocity(Vec2);
 
-#[derive(Component)]
+#[derive(Component, Reflect)]
+#[reflect(Component)]
 struct AnimationInd {
     walk: AnimIndices,
     idle: AnimIndices,
+    bored: Option<AnimIndices>,
+    start_walk: Option<AnimIndices>,
+    stop_walk: Option<AnimIndices>,
+}
+
+impl AnimationInd {
+    fn clip(&self, clip: AnimClip) -> Option<&AnimIndices> {
+        match clip {
+            AnimClip::Walk => Some(&self.walk),
+            AnimClip::Idle => Some(&self.idle),
+            AnimClip::Bored => self.bored.as_ref(),
+            AnimClip::StartWalk => self.start_walk.as_ref(),
+            AnimClip::StopWalk => self.stop_walk.as_ref(),
+        }
+    }
 }
 
-#[derive(Component, Deref, DerefMut)]
+#[derive(Component, Reflect, Deref, DerefMut)]
+#[reflect(Component)]
 struct AnimationTimer(Timer);
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-) {
-    // Load Textures
-    let sprite_texture: Handle<Image> = asset_server.load("spritesheet.png");
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct AnimationEvents(Vec<FrameTag>);
 
-    let atlas = TextureAtlasLayout::from_grid(UVec2::splat(16), 8, 8, None, None);
-    let texture_atlas_layouts = texture_atlas_layouts.add(atlas);
+// Plays the bored clip once after `after_secs` of standing still. It re-arms
+// only after another `after_secs` of idling, and moving cuts it short.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Boredom {
+    after_secs: f32,
+    idle_secs: f32,
+    playing: bool,
+}
 
-    let animation_indices = AnimationInd {
-        walk: AnimIndices {
-            right: 0,
-            left: 8,
-            up: 24,
-            down: 16,
-        },
-        idle: AnimIndices {
-            right: 32,
-            left: 40,
-            up: 56,
-            down: 48,
-        },
-    };
-    // Camera Spawn
-    commands.spawn((
-        Camera2dBundle::default(),
-        MainCameraTag,
-        CameraValues { lerp_factor: 2.0 },
-    ));
+impl Boredom {
+    fn update(&mut self, moving: bool, delta_seconds: f32) {
+        if moving {
+            self.idle_secs = 0.0;
+            self.playing = false;
+        } else if !self.playing {
+            self.idle_secs += delta_seconds;
+            if self.idle_secs >= self.after_secs {
+                self.idle_secs = 0.0;
+                self.playing = true;
+            }
+        }
+    }
+}
 
-    // UI
-    commands.spawn(
-        TextBundle::from_section("Welcome", TextStyle::default()).with_style(Style {
-            position_type: PositionType::Relative,
-            ..default()
-        }),
-    );
+// Plays the start_walk or stop_walk clip once when the entity starts or stops
+// walking, if its layout has one, to soften the cut between idle and walk
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct WalkTransition {
+    was_walking: bool,
+    playing: Option<AnimClip>,
+}
 
-    // Box
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(45.0, 45.0)).into(),
-            material: materials.add(Color::from(WHITE)),
-            transform: Transform {
-                translation: Vec3::new(200., 200., 0.),
-                ..default()
-            },
-            ..default()
-        },
-        RigidBody::Fixed,
-        Collider::cuboid(22.5, 22.5),
-        ColliderTag,
-    ));
+impl WalkTransition {
+    fn update(&mut self, walking: bool, indices: &AnimationInd) {
+        if walking != self.was_walking {
+            self.was_walking = walking;
+            let clip = if walking {
+                AnimClip::StartWalk
+            } else {
+                AnimClip::StopWalk
+            };
+            self.playing = indices.clip(clip).is_some().then_some(clip);
+        }
+    }
+}
 
-    // Box
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes.add(Rectangle::new(45.0, 45.0)).into(),
-            material: materials.add(Color::from(WHITE)),
-            transform: Transform {
-                translation: Vec3::new(-200., 200., 0.),
-                ..default()
-            },
-            ..default()
-        },
-        RigidBody::Fixed,
-        Collider::cuboid(22.05, 22.5),
-        ColliderTag,
-    ));
+// Times how long a one-shot clip has been playing, so one that never
+// reaches its last frame can't hold the entity in it forever
+#[derive(Component, Reflect, Default)]
+#[reflect(Component)]
+struct AnimationWatchdog {
+    clip: Option<AnimClip>,
+    elapsed: f32,
+}
 
-    // Player
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform::from_scale(Vec3::splat(3.)),
-            texture: sprite_texture,
-            ..default()
-        },
-        TextureAtlas {
-            layout: texture_atlas_layouts,
-            index: 0,
-        },
-        animation_indices,
-        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
-        PlayerTag,
-        MoveSettings {
-            is_walking: false,
-            speed: 5.0,
-            accel: 20.0,
-            fric: 15.0,
-        },
-        FaceDirection(FacingDirection::DOWN),
-        Velocity(Vec2::ZERO),
-        RigidBody::KinematicPositionBased,
-        Collider::ball(7.0),
-        KinematicCharacterController::default(),
-    ));
+impl AnimationWatchdog {
+    // True once `clip` has played for longer than `limit`
+    fn update(&mut self, clip: AnimClip, limit: Option<f32>, delta_seconds: f32) -> bool {
+        if self.clip != Some(clip) {
+            self.clip = Some(clip);
+            self.elapsed = 0.0;
+        }
+        self.elapsed += delta_seconds;
+        limit.is_some_and(|limit| self.elapsed > limit)
+    }
 }
 
-fn get_player_input(
-    mut player_vel: Query<(&mut Velocity, &mut MoveSettings, &mut FaceDirection), With<PlayerTag>>,
-    time: Res<Time>,
-    keyboard: Res<ButtonInput<KeyCode>>,
-) {
-    let (mut player_vel, mut move_settings, mut face_direction) = player_vel.single_mut();
-    let mut input_vector = Vec2::ZERO;
+// Rain drop or snowflake drifting down the screen, in fractions of the
+// viewport per second. Hidden particles are waiting to be reused.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct WeatherParticle {
+    velocity: Vec2,
+}
 
-    if keyboard.pressed(KeyCode::KeyA) {
-        input_vector.x = -1.0;
-        face_direction.0 = FacingDirection::LEFT;
-    }
-    if keyboard.pressed(KeyCode::KeyD) {
-        input_vector.x = 1.0;
-        face_direction.0 = FacingDirection::RIGHT;
-    }
-    if keyboard.pressed(KeyCode::KeyW) {
-        input_vector.y = 1.0;
-        face_direction.0 = FacingDirection::UP;
-    }
-    if keyboard.pressed(KeyCode::KeyS) {
-        input_vector.y = -1.0;
-        face_direction.0 = FacingDirection::DOWN;
-    }
+// Read out as dialogue when interacted with
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Sign {
+    lines: Vec<String>,
+}
 
-    input_vector = input_vector.normalize_or_zero();
+// Text box revealing `lines` one at a time, character by character.
+// Open while InputContext::Dialogue is on top.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Dialogue {
+    lines: Vec<String>,
+    chars_per_sec: f32,
+    line: usize,
+    // Characters of the current line shown so far
+    revealed: f32,
+}
 
-    if input_vector != Vec2::ZERO {
-        move_settings.is_walking = true;
-        player_vel.0 = player_vel.0.lerp(
-            input_vector * move_settings.speed,
-            move_settings.accel * time.delta_seconds(),
-        );
-    } else {
-        move_settings.is_walking = false;
+// Shown next to the cursor while hovering the entity's collider
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Tooltip {
+    text: String,
+}
 
-        player_vel.0 = player_vel
-            .0
-            .lerp(Vec2::ZERO, move_settings.fric * time.delta_seconds());
-    }
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Interactable {
+    range: f32,
 }
 
-fn apply_kinematics(mut entity_transforms: Query<(&mut KinematicCharacterController, &Velocity)>) {
-    for (mut transform, vel) in &mut entity_transforms {
-        transform.translation = Some(vel.0);
-    }
+// Saved with the level scene
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct LevelEntity;
+
+// Static box, its mesh and collider are added by spawn_level_boxes
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct LevelBox {
+    size: Vec2,
 }
 
-fn animate_sprites(
-    time: Res<Time>,
-    mut sprites: Query<(
-        &AnimationInd,
-        &mut AnimationTimer,
-        &mut TextureAtlas,
-        &MoveSettings,
-        &FaceDirection,
-    )>,
-) {
-    for (indices, mut timer, mut atlas, move_settings, face_direction) in &mut sprites {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            if move_settings.is_walking {
-                let dir_offset = match face_direction.0 {
-                    FacingDirection::LEFT => indices.walk.left,
-                    FacingDirection::RIGHT => indices.walk.right,
-                    FacingDirection::UP => indices.walk.up,
-                    FacingDirection::DOWN => indices.walk.down,
-                };
+// Makes a LevelBox passable while moving along `direction` from the side
+// opposite to it, so it can be dropped off but not climbed or walked into
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct OneWayLedge {
+    direction: Vec2,
+}
 
-                atlas.index = (atlas.index + 1) % 8 + dir_offset;
-            } else {
-                let dir_offset = match face_direction.0 {
-                    FacingDirection::LEFT => indices.idle.left,
-                    FacingDirection::RIGHT => indices.idle.right,
-                    FacingDirection::UP => indices.idle.up,
-                    FacingDirection::DOWN => indices.idle.down,
-                };
+// Pushable box, the preset picks how it slides and bounces
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct LevelCrate {
+    size: Vec2,
+    preset: CratePreset,
+}
 
-                atlas.index = (atlas.index + 1) % 4 + dir_offset;
-            }
+// Sensor region that slows down anything wading through it
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct WaterZone {
+    size: Vec2,
+    speed_mult: f32,
+}
+
+// Sensor region that slows down anything pushing through it and rustles
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct TallGrass {
+    size: Vec2,
+    speed_mult: f32,
+}
+
+// Drawn over the player, so it turns see-through while the player is inside
+// its bounds
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct FadeWhenOccluding;
+
+// Recomputed every step from the zones the entity overlaps
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct TerrainEffects {
+    speed_mult: f32,
+    in_water: bool,
+    in_grass: bool,
+}
+
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Ripple;
+
+// Leaf kicked up from tall grass, drifts at `velocity` pixels per second
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Rustle {
+    velocity: Vec2,
+}
+
+#[derive(Debug, Clone, Copy, Reflect)]
+enum TweenProperty {
+    Translation { from: Vec3, to: Vec3 },
+    Scale { from: Vec3, to: Vec3 },
+    // Of the entity's Sprite, or its ColorMaterial
+    Alpha { from: f32, to: f32 },
+}
+
+#[derive(Debug, Clone, Copy, Reflect)]
+enum TweenEasing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl TweenEasing {
+    // Maps linear progress from 0 to 1 onto the curve
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            TweenEasing::Linear => t,
+            TweenEasing::EaseIn => t * t,
+            TweenEasing::EaseOut => t * (2.0 - t),
+            TweenEasing::EaseInOut => t * t * (3.0 - 2.0 * t),
         }
     }
 }
 
-fn update_camera(
-    mut camera: Query<(&mut Transform, &CameraValues), (With<MainCameraTag>, Without<PlayerTag>)>,
-    player: Query<&Transform, (With<PlayerTag>, Without<MainCameraTag>)>,
-    time: Res<Time>,
-) {
-    let (mut camera_transform, camera_val) = camera.single_mut();
-    let player_transform = player.single();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+enum TweenRepeat {
+    // Removes the Tween once finished
+    Once,
+    // Jumps back to the start
+    Loop,
+    // Heads back the way it came
+    PingPong,
+}
 
-    let Vec3 { x, y, .. } = player_transform.translation;
-    let dir = Vec3::new(x, y, camera_transform.translation.z);
+// Drives one property between two values over `duration` seconds, sending a
+// TweenCompleted each time it reaches an end
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Tween {
+    property: TweenProperty,
+    duration: f32,
+    easing: TweenEasing,
+    repeat: TweenRepeat,
+    elapsed: f32,
+    // False while a ping-pong heads back from `to` to `from`
+    forward: bool,
+}
 
-    camera_transform.translation = camera_transform
-        .translation
-        .lerp(dir, time.delta_seconds() * camera_val.lerp_factor);
+impl Tween {
+    fn new(property: TweenProperty, duration: f32, easing: TweenEasing) -> Self {
+        Self {
+            property,
+            duration,
+            easing,
+            repeat: TweenRepeat::Once,
+            elapsed: 0.0,
+            forward: true,
+        }
+    }
+
+    fn repeating(self, repeat: TweenRepeat) -> Self {
+        Self { repeat, ..self }
+    }
+
+    // Moves `dt` seconds along, returning how far between `from` (0) and `to`
+    // (1) the property should be, eased, and whether an end was just reached
+    fn advance(&mut self, dt: f32) -> (f32, bool) {
+        self.elapsed += dt;
+        // Direction of the leg being played this frame, before any ping-pong flip
+        let forward = self.forward;
+        let mut progress = (self.elapsed / self.duration).min(1.0);
+        let finished = self.elapsed >= self.duration;
+        if finished {
+            match self.repeat {
+                TweenRepeat::Once => {}
+                TweenRepeat::Loop => {
+                    self.elapsed %= self.duration;
+                    progress = self.elapsed / self.duration;
+                }
+                TweenRepeat::PingPong => {
+                    self.elapsed %= self.duration;
+                    self.forward = !self.forward;
+                    // Finish this leg before heading back next frame
+                    progress = 1.0;
+                }
+            }
+        }
+        if !forward {
+            progress = 1.0 - progress;
+        }
+        (self.easing.apply(progress), finished)
+    }
+}
+
+// Volume of a playing sound before its bus is applied, kept so changing
+// AudioVolumes can rescale it
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct BusVolume {
+    bus: AudioBus,
+    volume: f32,
+}
+
+// Despawned by despawn_expired once the timer finishes
+#[derive(Component, Reflect, Deref, DerefMut)]
+#[reflect(Component)]
+struct Lifetime(Timer);
+
+impl Lifetime {
+    fn from_seconds(seconds: f32) -> Self {
+        Self(Timer::from_seconds(seconds, TimerMode::Once))
+    }
+}
+
+// Breaks a character controller free when it's wedged on a corner it should slide past
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct AntiStuck {
+    // A step is stuck if less than this fraction of the desired movement happened
+    min_progress: f32,
+    // Consecutive stuck steps before nudging
+    max_stuck_steps: u32,
+    // Pixels
+    nudge: f32,
+    stuck_steps: u32,
+}
+
+// Stretches the sprite along its motion when speeding up and squashes it when
+// slowing down, easing back to its normal shape
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct SquashStretch {
+    // Deformation per pixel/s² of acceleration
+    strength: f32,
+    // Most the sprite grows along one axis, 0.2 is 20% longer
+    max_deform: f32,
+    // How quickly the shape eases towards the target, per second
+    rate: f32,
+    last_velocity: Vec2,
+    // Multiplier on the sprite's size
+    deform: Vec2,
+}
+
+// Overlay darkening the parts of the level the player can't see, one texel of
+// its image per cell. Cells once seen stay dimmed rather than dark.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct FogOfWar {
+    cells: UVec2,
+    explored: Vec<bool>,
+}
+
+// Brightens the Lightmap around the entity, fading out at `radius` pixels
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Light2d {
+    radius: f32,
+    color: Color,
+    // Brightness at the centre, lights add up where they overlap
+    intensity: f32,
+}
+
+// Tiled background sprite kept around the camera. Factor is how fast it
+// scrolls across the screen compared to the level, below 1 looks distant.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct ParallaxLayer {
+    factor: Vec2,
+    // World size of one repeat of the texture
+    tile_size: Vec2,
+}
+
+// Replaced by a collider traced around the sprite's opaque pixels once its
+// image has loaded. For single images, not spritesheet frames.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct ColliderFromSprite {
+    // Pixels with at least this alpha count as solid
+    alpha_threshold: u8,
+}
+
+// Overlay darkening what Light2d doesn't reach, over the same area as the
+// fog of war and one texel per cell
+#[derive(Component)]
+struct Lightmap {
+    cells: UVec2,
+}
+
+// Material and mesh the entity had before being outlined
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct Highlighted {
+    original: Handle<ColorMaterial>,
+    original_mesh: Handle<Mesh>,
+}
+
+// Resources
+#[derive(Resource)]
+struct OutlineSettings {
+    color: Color,
+    // Fraction of the sprite size, drawn outside its edge
+    thickness: f32,
+}
+
+#[derive(Resource)]
+struct OcclusionFadeSettings {
+    // Alpha of something hiding the player
+    faded_alpha: f32,
+    // How quickly the alpha eases towards faded or back to opaque, per second
+    rate: f32,
+}
+
+#[derive(Resource)]
+struct OutlineShader(Handle<Shader>);
+
+#[derive(Resource)]
+struct GameAssets {
+    spritesheet: Handle<Image>,
+    player_animations: Handle<AnimationLayout>,
+    rock: Handle<Image>,
+    music: Handle<AudioSource>,
+}
+
+// Handles that have to finish loading before leaving GameState::Loading
+#[derive(Resource)]
+struct RequiredAssets(Vec<UntypedHandle>);
+
+// Preloaded sound and volume for each kind of sound effect
+#[derive(Resource)]
+struct SfxLibrary(HashMap<SfxKind, (Handle<AudioSource>, f32)>);
+
+#[derive(Resource)]
+struct SpatialAudioSettings {
+    // Pixels from the camera, sounds further away aren't played
+    max_range: f32,
+    // Exponent of the fade, 1 is linear and higher keeps sounds quiet until they're close
+    falloff: f32,
+}
+
+// Each 0 to 1, a sound plays at its own volume times master times its bus
+#[derive(Resource)]
+struct AudioVolumes {
+    master: f32,
+    music: f32,
+    sfx: f32,
+    ui: f32,
+}
+
+impl AudioVolumes {
+    fn gain(&self, bus: AudioBus) -> f32 {
+        let bus = match bus {
+            AudioBus::Music => self.music,
+            AudioBus::Sfx => self.sfx,
+            AudioBus::Ui => self.ui,
+        };
+        self.master.clamp(0.0, 1.0) * bus.clamp(0.0, 1.0)
+    }
+}
+
+impl SpatialAudioSettings {
+    fn volume_at(&self, distance: f32) -> Option<f32> {
+        (distance < self.max_range).then(|| (1.0 - distance / self.max_range).powf(self.falloff))
+    }
+}
+
+// Hints waiting to be shown on the HintText line, in order. Text can name
+// controls with Action placeholders, filled in for the last used device.
+#[derive(Resource, Default)]
+struct HintQueue {
+    pending: VecDeque<(String, f32)>,
+    // Text before placeholders are filled in, and its remaining time
+    current: Option<(String, Timer)>,
+}
+
+impl HintQueue {
+    fn show_hint(&mut self, text: impl Into<String>, duration: f32) {
+        self.pending.push_back((text.into(), duration));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+enum WeatherKind {
+    Clear,
+    Rain,
+    Snow,
+}
+
+// Saved with the level, so each level keeps its own weather
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct Weather {
+    kind: WeatherKind,
+    // 0 to 1, scales how many particles spawn
+    intensity: f32,
+    // Sideways drift, in viewport widths per second
+    wind: f32,
+}
+
+// Things the player has done that shouldn't happen again, saved with the
+// game. A list rather than a set, there are only ever a handful.
+#[derive(Resource, Reflect, Default)]
+#[reflect(Resource)]
+struct WorldFlags(Vec<String>);
+
+impl WorldFlags {
+    fn is_set(&self, flag: &str) -> bool {
+        self.0.iter().any(|set| set == flag)
+    }
+
+    fn set(&mut self, flag: &str) {
+        if !self.is_set(flag) {
+            self.0.push(flag.to_string());
+        }
+    }
+}
+
+// Establishing shot played when the level loads, saved with the level.
+// The camera pans over each waypoint in turn, then back to the player.
+// No waypoints means no intro.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct LevelIntro {
+    waypoints: Vec<Vec2>,
+    // Seconds the camera spends heading to and looking at each point
+    per_point_time: f32,
+}
+
+impl WeatherKind {
+    // Particles per second at full intensity, their fall speed in viewport
+    // heights per second, size in pixels and colour
+    fn particles(self) -> Option<(f32, f32, Vec2, Color)> {
+        match self {
+            WeatherKind::Clear => None,
+            WeatherKind::Rain => Some((
+                300.0,
+                1.5,
+                Vec2::new(2.0, 10.0),
+                Color::srgba(0.7, 0.8, 1.0, 0.6),
+            )),
+            WeatherKind::Snow => Some((80.0, 0.15, Vec2::splat(4.0), Color::WHITE)),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct DialogueSettings {
+    chars_per_sec: f32,
+}
+
+// Device the player last used, hints name its controls
+#[derive(Resource, Default, PartialEq)]
+struct LastInputDevice(InputDevice);
+
+// Slot F5 saves to and F9 loads from, 1 to SAVE_SLOTS
+#[derive(Resource)]
+struct SaveSlots {
+    selected: u8,
+}
+
+// Held menu directions move once straight away, again after `initial_delay`
+// seconds, then `repeat_rate` times a second, like key repeat
+#[derive(Resource)]
+struct NavRepeat {
+    initial_delay: f32,
+    repeat_rate: f32,
+}
+
+// How long the current direction has been held, for NavRepeat
+#[derive(Default)]
+struct NavRepeatState {
+    direction: i32,
+    held_secs: f32,
+    pulses: u32,
+}
+
+impl NavRepeatState {
+    /// Returns the direction on frames that should navigate, 0 otherwise.
+    /// Pulses missed during a long frame are dropped rather than bunched up.
+    fn update(&mut self, direction: i32, delta_seconds: f32, repeat: &NavRepeat) -> i32 {
+        if direction != self.direction {
+            *self = Self {
+                direction,
+                held_secs: 0.0,
+                pulses: 1,
+            };
+            return direction;
+        }
+        if direction == 0 {
+            return 0;
+        }
+
+        self.held_secs += delta_seconds;
+        let due = if self.held_secs < repeat.initial_delay {
+            1
+        } else {
+            2 + ((self.held_secs - repeat.initial_delay) * repeat.repeat_rate) as u32
+        };
+        if due > self.pulses {
+            self.pulses = due;
+            direction
+        } else {
+            0
+        }
+    }
+}
+
+// Logs frame time percentiles and the entity count every `interval`, for
+// catching performance regressions. Nothing runs while disabled.
+#[derive(Resource)]
+struct FrameLog {
+    enabled: bool,
+    interval: Timer,
+    // Records are also appended here when set
+    csv_path: Option<String>,
+    // Frame times since the last record
+    frame_ms: Vec<f32>,
+}
+
+// Loads the level with `enemies` wandering bodies, `projectiles` flying
+// sensors and `particles` drifting leaves, runs `frames` frames and reports
+// how long they took, then quits. Turned on by setting ROB_BENCHMARK, with
+// any counts given as "frames=600,enemies=200". It runs headless, so the
+// timings cover the game's systems and physics but no rendering.
+#[derive(Resource)]
+struct Benchmark {
+    enabled: bool,
+    frames: usize,
+    enemies: u32,
+    projectiles: u32,
+    particles: u32,
+    frame_ms: Vec<f32>,
+    // Filled in by run_benchmark once all frames have run
+    report: Option<BenchmarkReport>,
+}
+
+impl Benchmark {
+    fn from_env() -> Self {
+        Self::parse(std::env::var("ROB_BENCHMARK").ok().as_deref())
+    }
+
+    // `var` is ROB_BENCHMARK's value, None when it isn't set
+    fn parse(var: Option<&str>) -> Self {
+        let count = |key: &str, default| {
+            var.into_iter()
+                .flat_map(|var| var.split(','))
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim() == key)
+                .and_then(|(_, value)| value.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            enabled: var.is_some(),
+            frames: count("frames", 600) as usize,
+            enemies: count("enemies", 200),
+            projectiles: count("projectiles", 500),
+            particles: count("particles", 2000),
+            frame_ms: Vec::new(),
+            report: None,
+        }
+    }
+}
+
+// Frame times of a finished benchmark, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BenchmarkReport {
+    frames: usize,
+    average_ms: f32,
+    p95_ms: f32,
+    p99_ms: f32,
+    worst_ms: f32,
+    entities: u32,
+}
+
+// Stand-in for an enemy in the benchmark, turns around when it bumps into something
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct BenchmarkEnemy;
+
+// Periodically saves to AUTOSAVE_SLOT in `dir`, None turns it off
+#[derive(Resource)]
+struct AutoSave {
+    interval: Option<Timer>,
+    dir: String,
+}
+
+// Seconds of game time played, carried across saves
+#[derive(Resource, Default)]
+struct Playtime(f32);
+
+// Written next to each slot's scene, so slots can be listed without loading them
+#[derive(Debug, Serialize, Deserialize)]
+struct SlotInfo {
+    slot: u8,
+    playtime_secs: f32,
+    // Where the player was when saving
+    position: [f32; 2],
+    // Seconds since the Unix epoch
+    timestamp: u64,
+}
+
+// Multiplier on game time, 0 pauses. Real time (UI, input) isn't affected.
+#[derive(Resource)]
+struct TimeScale(f32);
+
+// Gizmo overlays for tuning gameplay, lighter than Rapier's debug renderer.
+// F6, F7 and F8 toggle them, in debug builds only. keys_enabled also turns on
+// spawning crates at the cursor with 1, 2 and 3, T teleporting the player to
+// the cursor, F4 freezing the player, F10 noclip and F framing the player
+// with the nearest crate.
+#[derive(Resource)]
+struct DebugFlags {
+    keys_enabled: bool,
+    // Character controller colliders
+    controllers: bool,
+    velocities: bool,
+    interact_ranges: bool,
+}
+
+// Safety net for runaway spawning, warns when more entities carry a marker
+// than its limit. Only enabled in debug builds.
+#[derive(Resource)]
+struct EntityBudget {
+    enabled: bool,
+    limits: HashMap<TypeId, usize>,
+}
+
+// Collision shape the player spawns with, picked by setting ROB_PLAYER_COLLIDER
+#[derive(Resource)]
+struct PlayerCollider(ColliderShape);
+
+impl PlayerCollider {
+    fn from_env() -> Self {
+        Self::parse(std::env::var("ROB_PLAYER_COLLIDER").ok().as_deref())
+    }
+
+    // `var` is ROB_PLAYER_COLLIDER's value, ball, capsule or cuboid. Anything
+    // else gets the ball.
+    fn parse(var: Option<&str>) -> Self {
+        let shape = match var.map(str::trim) {
+            None | Some("ball") => ColliderShape::Ball { radius: 7.0 },
+            Some("capsule") => ColliderShape::Capsule {
+                half_height: 3.0,
+                radius: 5.0,
+            },
+            Some("cuboid") => ColliderShape::Cuboid {
+                half_size: Vec2::new(6.0, 7.0),
+            },
+            Some(other) => {
+                warn!("Unknown player collider {other:?}, using the ball");
+                ColliderShape::Ball { radius: 7.0 }
+            }
+        };
+        Self(shape)
+    }
+}
+
+// In texels, scaled up with the sprite
+#[derive(Debug, Clone, Copy)]
+enum ColliderShape {
+    Ball { radius: f32 },
+    // Upright, half_height is from the centre to the middle of either cap
+    Capsule { half_height: f32, radius: f32 },
+    Cuboid { half_size: Vec2 },
+}
+
+impl ColliderShape {
+    fn collider(self) -> Collider {
+        match self {
+            ColliderShape::Ball { radius } => Collider::ball(radius),
+            ColliderShape::Capsule {
+                half_height,
+                radius,
+            } => Collider::capsule_y(half_height, radius),
+            ColliderShape::Cuboid { half_size } => Collider::cuboid(half_size.x, half_size.y),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct FogSettings {
+    // Area covered by the fog, centred on the origin, in pixels
+    extent: Vec2,
+    cell_size: f32,
+    // Pixels around the player that are revealed
+    vision_radius: f32,
+    // Darkness of explored cells out of sight, 1 is as dark as unexplored
+    explored_alpha: f32,
+}
+
+impl FogSettings {
+    fn cells(&self) -> UVec2 {
+        (self.extent / self.cell_size).ceil().as_uvec2()
+    }
+
+    // World position of a cell's centre, rows run top to bottom like the image
+    fn cell_center(&self, cell: UVec2) -> Vec2 {
+        let offset = (cell.as_vec2() + 0.5) * self.cell_size;
+        Vec2::new(
+            -self.extent.x / 2. + offset.x,
+            self.extent.y / 2. - offset.y,
+        )
+    }
+}
+
+#[derive(Resource)]
+struct LightingSettings {
+    // How dark unlit cells get, 0 turns lighting off and 1 is black
+    darkness: f32,
+    cell_size: f32,
+    // Only this many lights closest to the camera are drawn
+    max_lights: usize,
+}
+
+// Colliders traced by collider_from_image, per image and alpha threshold.
+// None for images with nothing to collide with.
+#[derive(Resource, Default)]
+struct SpriteColliders(HashMap<(AssetId<Image>, u8), Option<Collider>>);
+
+// Sprite colour for each faction, factions without an entry aren't tinted
+#[derive(Resource)]
+struct FactionTints(HashMap<Faction, Color>);
+
+impl FactionTints {
+    fn tint(&self, faction: Faction) -> Color {
+        self.0.get(&faction).copied().unwrap_or(Color::WHITE)
+    }
+}
+
+// Rounds velocities, steps and controller positions to multiples of `step`
+// pixels every fixed update, so small float differences between machines
+// get rounded away instead of building up over a replay. Smaller steps are
+// smoother but leave more room for differences to survive the rounding. At
+// 1/256 and 60 updates a second, anything slower than about 0.12 pixels per
+// second doesn't move at all.
+#[derive(Resource)]
+struct MoveQuantization {
+    enabled: bool,
+    step: f32,
+}
+
+impl MoveQuantization {
+    fn apply(&self, value: Vec2) -> Vec2 {
+        if self.enabled {
+            (value / self.step).round() * self.step
+        } else {
+            value
+        }
+    }
+}
+
+// Crates hitting something hard enough shake the camera
+#[derive(Resource)]
+struct ImpactShakeSettings {
+    // Contact force (Rapier units) below which impacts are ignored
+    min_force: f32,
+    // Shake intensity in pixels per unit of contact force
+    pixels_per_force: f32,
+}
+
+// Lowers the resolution the world renders at while frames take longer than
+// target_frame_ms, and raises it again once there's headroom. The UI is
+// drawn at full resolution on top.
+#[derive(Resource)]
+struct DynamicResolution {
+    target_frame_ms: f32,
+    // Fractions of the window's physical resolution
+    min_scale: f32,
+    max_scale: f32,
+    scale: f32,
+}
+
+impl DynamicResolution {
+    /// Steps down above the target and only back up well under it, so a frame
+    /// time near the target doesn't flip the scale back and forth.
+    fn next_scale(&self, frame_ms: f32) -> f32 {
+        let scale = if frame_ms > self.target_frame_ms * 1.1 {
+            self.scale - RENDER_SCALE_STEP
+        } else if frame_ms < self.target_frame_ms * 0.75 {
+            self.scale + RENDER_SCALE_STEP
+        } else {
+            self.scale
+        };
+        scale.clamp(self.min_scale, self.max_scale)
+    }
+}
+
+// The HUD is laid out for `reference_size` (logical pixels) and scaled with
+// the window from there
+#[derive(Resource)]
+struct UiScaleSettings {
+    reference_size: Vec2,
+    min_scale: f32,
+    max_scale: f32,
+}
+
+impl UiScaleSettings {
+    // Fits the reference size inside the window, so nothing is pushed off screen
+    fn scale_for(&self, window_size: Vec2) -> f32 {
+        (window_size / self.reference_size)
+            .min_element()
+            .clamp(self.min_scale, self.max_scale)
+    }
+}
+
+// Stack of input contexts, only the one on top handles input. Gameplay is
+// always at the bottom.
+#[derive(Resource)]
+struct InputContexts(Vec<InputContext>);
+
+impl Default for InputContexts {
+    fn default() -> Self {
+        Self(vec![InputContext::Gameplay])
+    }
+}
+
+impl InputContexts {
+    fn push(&mut self, context: InputContext) {
+        self.0.push(context);
+    }
+
+    // Gameplay at the bottom of the stack is never popped
+    fn pop(&mut self) -> Option<InputContext> {
+        if self.0.len() > 1 {
+            self.0.pop()
+        } else {
+            None
+        }
+    }
+
+    fn is_active(&self, context: InputContext) -> bool {
+        self.0.last() == Some(&context)
+    }
+}
+
+#[derive(Resource)]
+struct InputSettings {
+    // Stick magnitude below which gamepad input is ignored
+    stick_dead_zone: f32,
+}
+
+// World units are screen pixels. Pixel art is drawn with each texel
+// covering `texel_size` pixels, and physics treats `texels_per_meter`
+// texels as one meter. Sizes written in texels go through `texels()` so
+// art, colliders and Rapier's meter all move together when the scale
+// changes. Colliders on sprite entities inherit the sprite's scale, so
+// those are given directly in texels instead.
+struct WorldScale {
+    texel_size: f32,
+    texels_per_meter: f32,
+}
+
+impl WorldScale {
+    const fn pixels_per_meter(&self) -> f32 {
+        self.texel_size * self.texels_per_meter
+    }
+
+    // Converts a length in texels to world pixels
+    const fn texels(&self, texels: f32) -> f32 {
+        texels * self.texel_size
+    }
+}
+
+// 10 pixels per meter, which the crate presets and impulses are tuned for
+const WORLD_SCALE: WorldScale = WorldScale {
+    texel_size: 3.0,
+    texels_per_meter: 10.0 / 3.0,
+};
+
+// Collision group ledges live in, so controllers can filter them out
+const LEDGE_GROUP: Group = Group::GROUP_2;
+
+// Scale every sprite is drawn at, one texel per texel_size pixels
+const SPRITE_SCALE: f32 = WORLD_SCALE.texel_size;
+
+// Transparent square texture with a scattering of single pixel specks, the
+// same for the same seed
+fn speckle_tile(size: u32, density: f32, color: Color, seed: u32) -> Image {
+    let [r, g, b, _] = color.to_srgba().to_u8_array();
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    let mut state = seed.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+    for _ in 0..size * size {
+        state = state.wrapping_mul(747_796_405).wrapping_add(2_891_336_453);
+        let speck = ((state >> 8) as f32 / (1 << 24) as f32) < density;
+        data.extend_from_slice(&[r, g, b, if speck { 255 } else { 0 }]);
+    }
+    Image::new(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    )
+}
+
+// Transform for a sprite spawned at `translation`, pass a scale other than
+// SPRITE_SCALE only for art drawn at a different resolution
+fn sprite_transform(translation: Vec3, scale: Option<f32>) -> Transform {
+    Transform::from_translation(translation).with_scale(Vec3::splat(scale.unwrap_or(SPRITE_SCALE)))
+}
+
+// Side length of one frame in the spritesheet, in texels
+const TILE_TEXELS: u32 = 16;
+
+// Distance between HUD elements and the window edges
+const HUD_MARGIN: f32 = 12.0;
+
+// How quickly the camera bob follows changes in walking speed, per second
+const BOB_EASE_RATE: f32 = 6.0;
+
+// Most weather particles alive at once, reused once they leave the screen
+const MAX_WEATHER_PARTICLES: usize = 400;
+
+// Distance from the cursor to the tooltip's top left corner
+const TOOLTIP_OFFSET: Vec2 = Vec2::new(16.0, 16.0);
+
+// How long a hint takes to fade out at the end of its duration
+const HINT_FADE_SECS: f32 = 0.5;
+
+// Volume of the music loop before the music bus is applied
+const MUSIC_VOLUME: f32 = 0.5;
+
+// Game time each benchmark frame advances, whatever the frame really took
+const BENCHMARK_STEP: Duration = Duration::from_micros(16_667);
+
+// How much DynamicResolution changes the scale at a time, and how often
+const RENDER_SCALE_STEP: f32 = 0.1;
+const RENDER_SCALE_INTERVAL_SECS: f32 = 1.0;
+// Texels a traced outline may be off by when simplifying it
+const COLLIDER_TRACE_TOLERANCE: f32 = 0.75;
+
+// Only the presenting camera and its sprite are on this layer
+const PRESENT_LAYER: usize = 1;
+
+const RIPPLE_INTERVAL_SECS: f32 = 0.25;
+const RUSTLE_INTERVAL_SECS: f32 = 0.4;
+// Distance from the player's origin down to their feet
+const RIPPLE_FEET_OFFSET: f32 = 16.0;
+
+const SAVE_DIR: &str = "assets/saves";
+const SAVE_SLOTS: u8 = 3;
+// Written by auto_save, never picked with F1-F3
+const AUTOSAVE_SLOT: u8 = 0;
+
+// Materials
+const OUTLINE_SHADER_PATH: &str = "shaders/outline.wgsl";
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+struct OutlineMaterial {
+    #[uniform(0)]
+    color: LinearRgba,
+    #[uniform(1)]
+    outline_color: LinearRgba,
+    #[uniform(2)]
+    thickness: f32,
+    #[texture(3)]
+    #[sampler(4)]
+    texture: Option<Handle<Image>>,
+}
+
+impl Material2d for OutlineMaterial {
+    fn fragment_shader() -> ShaderRef {
+        OUTLINE_SHADER_PATH.into()
+    }
+}
+
+// Top-down, nothing should fall towards the bottom of the screen
+fn disable_gravity(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.gravity = Vec2::ZERO;
+}
+
+fn load_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // Load Textures
+    let spritesheet: Handle<Image> = asset_server.load("spritesheet.png");
+    // Not required, highlighting is skipped if it fails to load. One that loads
+    // but fails to compile only gets Bevy's pipeline error in the log.
+    commands.insert_resource(OutlineShader(asset_server.load(OUTLINE_SHADER_PATH)));
+    let player_animations: Handle<AnimationLayout> =
+        asset_server.load("animations/player.anim.ron");
+    commands.insert_resource(RequiredAssets(vec![
+        spritesheet.clone().untyped(),
+        player_animations.clone().untyped(),
+    ]));
+    // Not required either, the rock gets its collider whenever it loads and
+    // the music starts once it has
+    let rock = asset_server.load("props/rock.png");
+    let music = asset_server.load("sounds/music.wav");
+    commands.insert_resource(GameAssets {
+        spritesheet,
+        player_animations,
+        rock,
+        music,
+    });
+    commands.insert_resource(SfxLibrary(HashMap::from([
+        (
+            SfxKind::Footstep,
+            (asset_server.load("sounds/footstep.wav"), 0.3),
+        ),
+        (
+            SfxKind::Splash,
+            (asset_server.load("sounds/splash.wav"), 0.6),
+        ),
+        (
+            SfxKind::Rustle,
+            (asset_server.load("sounds/rustle.wav"), 0.4),
+        ),
+        (
+            SfxKind::UiSelect,
+            (asset_server.load("sounds/ui_select.wav"), 0.5),
+        ),
+    ])));
+
+    // Camera Spawn
+    // The world renders to an image that resize_scene_target keeps sized to
+    // the window and render scale. A second camera shows it along with the UI.
+    let mut scene_target = Image::new_fill(
+        Extent3d {
+            width: 1,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Bgra8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    scene_target.texture_descriptor.usage =
+        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
+    let scene_target = images.add(scene_target);
+    commands.spawn((
+        SpriteBundle {
+            texture: scene_target.clone(),
+            ..default()
+        },
+        ScenePresenter,
+        RenderLayers::layer(PRESENT_LAYER),
+    ));
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            ..default()
+        },
+        RenderLayers::layer(PRESENT_LAYER),
+    ));
+
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(scene_target),
+                ..default()
+            },
+            ..default()
+        },
+        MainCameraTag,
+        CameraValues {
+            lerp_factor: Vec2::splat(2.0),
+            easing: CameraEasing::Exponential,
+            follow_velocity: Vec2::ZERO,
+            snap: false,
+            screen_offset: Vec2::ZERO,
+            peek_reach: 0.3,
+            peek_rate: 4.0,
+            peek_offset: Vec2::ZERO,
+            target_rotation: 0.0,
+            rotation_rate: 3.0,
+            zoom_rate: 2.0,
+            camera_bob: 2.0,
+            bob_strength: 0.0,
+            bob_offset: Vec2::ZERO,
+        },
+        ScreenShake {
+            max_intensity: 12.0,
+            decay: 40.0,
+            intensity: 0.0,
+            offset: Vec2::ZERO,
+        },
+    ));
+
+    // Splash
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("Loading...", TextStyle::default()),
+                LoadingText,
+            ));
+        });
+}
+
+fn check_assets_loaded(
+    required: Res<RequiredAssets>,
+    asset_server: Res<AssetServer>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut loading_text: Query<&mut Text, With<LoadingText>>,
+    mut failed: Local<bool>,
+) {
+    if *failed {
+        return;
+    }
+
+    for handle in &required.0 {
+        if let LoadState::Failed(err) = asset_server.load_state(handle.id()) {
+            error!("Failed to load a required asset: {err}");
+            loading_text.single_mut().sections[0].value =
+                "Failed to load assets, see the log for details".to_string();
+            *failed = true;
+            return;
+        }
+    }
+
+    if required
+        .0
+        .iter()
+        .all(|handle| asset_server.is_loaded_with_dependencies(handle.id()))
+    {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn despawn_loading_screen(mut commands: Commands, screens: Query<Entity, With<LoadingScreen>>) {
+    for screen in &screens {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    animation_layouts: Res<Assets<AnimationLayout>>,
+    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    mut images: ResMut<Assets<Image>>,
+    fog_settings: Res<FogSettings>,
+    player_collider: Res<PlayerCollider>,
+    lighting: Res<LightingSettings>,
+    mut hints: ResMut<HintQueue>,
+) {
+    let layout = animation_layouts
+        .get(&game_assets.player_animations)
+        .expect("required assets are loaded before setup");
+    let atlas = TextureAtlasLayout::from_grid(
+        UVec2::splat(TILE_TEXELS),
+        layout.columns,
+        layout.rows,
+        None,
+        None,
+    );
+    let texture_atlas_layouts = texture_atlas_layouts.add(atlas);
+
+    let animation_indices = layout.indices();
+    let animation_events = layout.events();
+    let frame_time = animation_indices.idle.frame_time;
+    // UI
+    // Anchored to the window edges so Bevy's layout keeps it in place on resize
+    commands.spawn((
+        TextBundle::from_section("", TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(HUD_MARGIN),
+            left: Val::Px(HUD_MARGIN),
+            max_width: Val::Percent(50.0),
+            ..default()
+        }),
+        HintText,
+    ));
+    commands.spawn((
+        TextBundle::from_section("", TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            ..default()
+        }),
+        TooltipText,
+    ));
+    hints.show_hint("Welcome", 2.0);
+    hints.show_hint("{move} to move, {peek} to look ahead", 4.0);
+
+    // Boxes, the left one is a sign
+    for x in [200., -200.] {
+        let mut level_box = commands.spawn((
+            LevelEntity,
+            LevelBox {
+                size: Vec2::splat(WORLD_SCALE.texels(15.0)),
+            },
+            Transform::from_xyz(x, 200., 0.),
+            ColliderTag,
+            Interactable { range: 80.0 },
+        ));
+        if x < 0. {
+            level_box.insert(Sign {
+                lines: vec![
+                    "The pond to the south is deep.".to_string(),
+                    "Wading through it is slow going.".to_string(),
+                ],
+            });
+        }
+    }
+
+    // Ledge, dropped off southwards
+    commands.spawn((
+        LevelEntity,
+        LevelBox {
+            size: Vec2::new(WORLD_SCALE.texels(40.0), WORLD_SCALE.texels(4.0)),
+        },
+        OneWayLedge {
+            direction: Vec2::NEG_Y,
+        },
+        Transform::from_xyz(300., -60., 0.),
+    ));
+
+    // Crates
+    for (x, preset) in [
+        (-120., CratePreset::Heavy),
+        (0., CratePreset::Slippery),
+        (120., CratePreset::Bouncy),
+    ] {
+        commands.spawn((
+            LevelEntity,
+            LevelCrate {
+                size: Vec2::splat(WORLD_SCALE.texels(10.0)),
+                preset,
+            },
+            Transform::from_xyz(x, 90., 0.),
+        ));
+    }
+
+    // Pond
+    commands.spawn((
+        LevelEntity,
+        WaterZone {
+            size: Vec2::new(WORLD_SCALE.texels(80.0), WORLD_SCALE.texels(48.0)),
+            speed_mult: 0.5,
+        },
+        Transform::from_xyz(0., -200., -1.),
+    ));
+
+    // Background
+    for (seed, factor, color, z) in [
+        (1, 0.2, Color::srgb(0.35, 0.4, 0.5), -20.),
+        (2, 0.5, Color::srgb(0.5, 0.55, 0.45), -10.),
+    ] {
+        let tile = speckle_tile(32, 0.02, color, seed);
+        let tile_size = tile.size().as_vec2() * SPRITE_SCALE;
+        commands.spawn((
+            SpriteBundle {
+                texture: images.add(tile),
+                transform: Transform::from_xyz(0., 0., z),
+                ..default()
+            },
+            ImageScaleMode::Tiled {
+                tile_x: true,
+                tile_y: true,
+                stretch_value: SPRITE_SCALE,
+            },
+            ParallaxLayer {
+                factor: Vec2::splat(factor),
+                tile_size,
+            },
+        ));
+    }
+
+    // Rock, with a collider traced around its sprite
+    commands.spawn((
+        SpriteBundle {
+            texture: game_assets.rock.clone(),
+            transform: sprite_transform(Vec3::new(-90., -60., 0.), None),
+            ..default()
+        },
+        ColliderFromSprite {
+            alpha_threshold: 128,
+        },
+    ));
+
+    // Torch by the pond
+    commands.spawn((
+        LevelEntity,
+        Light2d {
+            radius: 140.0,
+            color: Color::srgb(1.0, 0.6, 0.25),
+            intensity: 1.2,
+        },
+        Transform::from_xyz(160., -160., 0.),
+    ));
+
+    // Tall grass, overlapping a corner of the pond, hides the player wading through
+    commands.spawn((
+        LevelEntity,
+        TallGrass {
+            size: Vec2::new(WORLD_SCALE.texels(60.0), WORLD_SCALE.texels(40.0)),
+            speed_mult: 0.7,
+        },
+        FadeWhenOccluding,
+        Transform::from_xyz(-200., -160., 1.),
+    ));
+
+    // Fog of war, drawn over the level
+    let fog_cells = fog_settings.cells();
+    let fog_image = Image::new_fill(
+        Extent3d {
+            width: fog_cells.x,
+            height: fog_cells.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(fog_settings.extent),
+                ..default()
+            },
+            texture: images.add(fog_image),
+            transform: Transform::from_xyz(0., 0., 10.),
+            ..default()
+        },
+        FogOfWar {
+            cells: fog_cells,
+            explored: vec![false; (fog_cells.x * fog_cells.y) as usize],
+        },
+    ));
+
+    // Lightmap, under the fog
+    let light_cells = (fog_settings.extent / lighting.cell_size).ceil().as_uvec2();
+    let mut light_image = Image::new_fill(
+        Extent3d {
+            width: light_cells.x,
+            height: light_cells.y,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 0],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    // Blends between cells so light falls off smoothly
+    light_image.sampler = ImageSampler::linear();
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(fog_settings.extent),
+                ..default()
+            },
+            texture: images.add(light_image),
+            transform: Transform::from_xyz(0., 0., 9.),
+            ..default()
+        },
+        Lightmap { cells: light_cells },
+    ));
+
+    // Player
+    commands.spawn((
+        SpriteBundle {
+            transform: sprite_transform(Vec3::ZERO, None),
+            texture: game_assets.spritesheet.clone(),
+            ..default()
+        },
+        TextureAtlas {
+            layout: texture_atlas_layouts,
+            index: 0,
+        },
+        (
+            animation_indices,
+            animation_events,
+            AnimationTimer(Timer::from_seconds(frame_time, TimerMode::Repeating)),
+            Boredom {
+                after_secs: 10.0,
+                idle_secs: 0.0,
+                playing: false,
+            },
+            WalkTransition::default(),
+            AnimationWatchdog::default(),
+            SquashStretch {
+                strength: 0.0001,
+                max_deform: 0.2,
+                rate: 12.0,
+                last_velocity: Vec2::ZERO,
+                deform: Vec2::ONE,
+            },
+        ),
+        Light2d {
+            radius: 180.0,
+            color: Color::srgb(1.0, 0.95, 0.85),
+            intensity: 1.0,
+        },
+        PlayerTag,
+        CameraTarget,
+        MoveSettings {
+            is_walking: false,
+            speed: 320.0,
+            accel: 20.0,
+            fric: 15.0,
+            turn_decel: 40.0,
+            normalize_diagonal: true,
+            stop_speed: 2.0,
+        },
+        FaceDirection(FacingDirection::Down),
+        Faction::Player,
+        Velocity(Vec2::ZERO),
+        TerrainEffects {
+            speed_mult: 1.0,
+            in_water: false,
+            in_grass: false,
+        },
+        RigidBody::KinematicPositionBased,
+        player_collider.0.collider(),
+        KinematicCharacterController::default(),
+        AntiStuck {
+            min_progress: 0.05,
+            max_stuck_steps: 8,
+            nudge: 1.0,
+            stuck_steps: 0,
+        },
+    ));
+}
+
+/// Outside the gameplay context the player gets no input and slows to a stop.
+/// A frozen player is left alone entirely, keeping its velocity.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn get_player_input(
+    mut player_vel: Query<
+        (
+            &mut Velocity,
+            &mut MoveSettings,
+            &mut FaceDirection,
+            &TerrainEffects,
+        ),
+        (With<PlayerTag>, Without<Frozen>),
+    >,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    input_settings: Res<InputSettings>,
+    contexts: Res<InputContexts>,
+    quantization: Res<MoveQuantization>,
+    mut axes: Local<(HeldAxis, HeldAxis)>,
+) {
+    let Ok((mut player_vel, mut move_settings, mut face_direction, terrain)) =
+        player_vel.get_single_mut()
+    else {
+        return;
+    };
+    let gameplay = contexts.is_active(InputContext::Gameplay);
+    // Keys held while suppressed count as newly pressed once gameplay resumes
+    let held = |key| gameplay && keyboard.pressed(key);
+    let (x_axis, y_axis) = &mut *axes;
+    let mut input_vector = Vec2::new(
+        x_axis.update(held(KeyCode::KeyA), held(KeyCode::KeyD)),
+        y_axis.update(held(KeyCode::KeyS), held(KeyCode::KeyW)),
+    );
+
+    if input_vector.x < 0.0 {
+        face_direction.0 = FacingDirection::Left;
+    } else if input_vector.x > 0.0 {
+        face_direction.0 = FacingDirection::Right;
+    }
+    if input_vector.y > 0.0 {
+        face_direction.0 = FacingDirection::Up;
+    } else if input_vector.y < 0.0 {
+        face_direction.0 = FacingDirection::Down;
+    }
+
+    if move_settings.normalize_diagonal {
+        input_vector = input_vector.normalize_or_zero();
+    }
+
+    if input_vector == Vec2::ZERO && gameplay {
+        if let Some(gamepad) = gamepads.iter().next() {
+            let axis = |axis_type| {
+                gamepad_axes
+                    .get(GamepadAxis::new(gamepad, axis_type))
+                    .unwrap_or(0.0)
+            };
+            let stick = Vec2::new(
+                axis(GamepadAxisType::LeftStickX),
+                axis(GamepadAxisType::LeftStickY),
+            );
+            input_vector = apply_dead_zone(stick, input_settings.stick_dead_zone);
+
+            if input_vector != Vec2::ZERO {
+                face_direction.0 = if input_vector.x.abs() > input_vector.y.abs() {
+                    if input_vector.x < 0.0 {
+                        FacingDirection::Left
+                    } else {
+                        FacingDirection::Right
+                    }
+                } else if input_vector.y < 0.0 {
+                    FacingDirection::Down
+                } else {
+                    FacingDirection::Up
+                };
+            }
+        }
+    }
+
+    move_settings.is_walking = input_vector != Vec2::ZERO;
+    let velocity = step_velocity(
+        player_vel.0,
+        input_vector,
+        &move_settings,
+        terrain.speed_mult,
+        time.delta_seconds(),
+    );
+    player_vel.0 = quantization.apply(velocity);
+}
+
+/// Eases the velocity towards `input * speed`, using turn_decel while the
+/// input opposes it and fric to come to a stop without input.
+fn step_velocity(
+    velocity: Vec2,
+    input: Vec2,
+    settings: &MoveSettings,
+    speed_mult: f32,
+    delta_seconds: f32,
+) -> Vec2 {
+    if input == Vec2::ZERO {
+        let velocity = velocity.lerp(Vec2::ZERO, smoothing_factor(settings.fric, delta_seconds));
+        // The lerp only approaches zero, held input is never snapped
+        return if velocity.length() < settings.stop_speed {
+            Vec2::ZERO
+        } else {
+            velocity
+        };
+    }
+    let rate = if input.dot(velocity) < 0.0 {
+        settings.turn_decel
+    } else {
+        settings.accel
+    };
+    velocity.lerp(
+        input * settings.speed * speed_mult,
+        smoothing_factor(rate, delta_seconds),
+    )
+}
+
+/// Resizes the sprite rather than scaling the transform, which would scale the
+/// collider with it. The shape keeps its area, growing along the axis of
+/// motion and shrinking across it or the other way round.
+fn squash_and_stretch(
+    mut sprites: Query<(&mut Sprite, &mut SquashStretch, &Velocity), Without<Frozen>>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    if dt <= 0.0 {
+        return;
+    }
+    for (mut sprite, mut squash, velocity) in &mut sprites {
+        let previous = std::mem::replace(&mut squash.last_velocity, velocity.0);
+        let acceleration = (velocity.0 - previous) / dt;
+
+        let amount = (acceleration.length() * squash.strength).min(squash.max_deform);
+        // Speeding up stretches, braking squashes
+        let along = if velocity.0.length_squared() >= previous.length_squared() {
+            1.0 + amount
+        } else {
+            1.0 / (1.0 + amount)
+        };
+        let motion = if velocity.0 == Vec2::ZERO {
+            acceleration
+        } else {
+            velocity.0
+        };
+        let target = if motion.x.abs() > motion.y.abs() {
+            Vec2::new(along, 1.0 / along)
+        } else {
+            Vec2::new(1.0 / along, along)
+        };
+        let t = smoothing_factor(squash.rate, dt);
+        squash.deform = squash.deform.lerp(target, t);
+
+        let resting = squash.deform.abs_diff_eq(Vec2::ONE, 0.001);
+        sprite.custom_size = (!resting).then(|| Vec2::splat(TILE_TEXELS as f32) * squash.deform);
+    }
+}
+
+fn update_terrain_effects(
+    rapier_context: Res<RapierContext>,
+    mut entities: Query<(Entity, &mut TerrainEffects)>,
+    water: Query<&WaterZone>,
+    grass: Query<&TallGrass>,
+    mut sfx: EventWriter<SfxEvent>,
+) {
+    for (entity, mut terrain) in &mut entities {
+        let mut speed_mult = 1.0;
+        let mut in_water = false;
+        let mut in_grass = false;
+
+        for (a, b, intersecting) in rapier_context.intersection_pairs_with(entity) {
+            let other = if a == entity { b } else { a };
+            if !intersecting {
+                continue;
+            }
+            if let Ok(zone) = water.get(other) {
+                speed_mult *= zone.speed_mult;
+                in_water = true;
+            }
+            if let Ok(grass) = grass.get(other) {
+                speed_mult *= grass.speed_mult;
+                in_grass = true;
+            }
+        }
+
+        if in_water && !terrain.in_water {
+            sfx.send(SfxEvent {
+                kind: SfxKind::Splash,
+            });
+        }
+        terrain.speed_mult = speed_mult;
+        terrain.in_water = in_water;
+        terrain.in_grass = in_grass;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn apply_kinematics(
+    mut entity_transforms: Query<(
+        &mut KinematicCharacterController,
+        &mut Transform,
+        &Velocity,
+        Has<Frozen>,
+        Has<Noclip>,
+    )>,
+    time: Res<Time>,
+    quantization: Res<MoveQuantization>,
+) {
+    for (mut controller, mut transform, vel, frozen, noclip) in &mut entity_transforms {
+        let motion = (!frozen).then(|| quantization.apply(vel.0 * time.delta_seconds()));
+        if noclip {
+            transform.translation += motion.unwrap_or_default().extend(0.0);
+            controller.translation = None;
+        } else {
+            controller.translation = motion;
+        }
+    }
+}
+
+/// Rapier resolves collisions with unrounded floats, this rounds what it
+/// wrote back before the next step builds on it.
+fn quantize_positions(
+    mut controllers: Query<&mut Transform, With<KinematicCharacterController>>,
+    quantization: Res<MoveQuantization>,
+) {
+    for mut transform in &mut controllers {
+        let position = quantization.apply(transform.translation.truncate());
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+/// A touched ledge blocks unless the controller moves along its direction and
+/// starts fully on the far side of it. Ledges the controller already overlaps
+/// never block, so one straddling the edge can finish crossing. The filter
+/// covers the whole step, so any blocking ledge blocks all of them.
+fn pass_one_way_ledges(
+    rapier_context: Res<RapierContext>,
+    mut controllers: Query<(
+        &mut KinematicCharacterController,
+        &Collider,
+        &GlobalTransform,
+    )>,
+    ledges: Query<(&OneWayLedge, &LevelBox, &GlobalTransform)>,
+) {
+    let is_ledge = |entity| ledges.contains(entity);
+    let filter = QueryFilter::new().predicate(&is_ledge);
+    for (mut controller, collider, transform) in &mut controllers {
+        let position = transform.translation().truncate();
+        let motion = controller.translation.unwrap_or_default();
+
+        let mut straddling = Vec::new();
+        rapier_context.intersections_with_shape(position, 0.0, collider, filter, |entity| {
+            straddling.push(entity);
+            true
+        });
+
+        let mut blocked = false;
+        rapier_context.intersections_with_shape(
+            position + motion,
+            0.0,
+            collider,
+            filter,
+            |entity| {
+                let Ok((ledge, level_box, ledge_transform)) = ledges.get(entity) else {
+                    return true;
+                };
+                if straddling.contains(&entity) {
+                    return true;
+                }
+                let direction = ledge.direction.normalize_or_zero();
+                // Half the ledge's thickness along its direction
+                let half_depth = (level_box.size / 2. * direction).abs().element_sum();
+                let depth = (position - ledge_transform.translation().truncate()).dot(direction);
+                blocked = motion.dot(direction) <= 0. || depth > -half_depth;
+                !blocked
+            },
+        );
+
+        controller.filter_groups =
+            (!blocked).then(|| CollisionGroups::new(Group::ALL, !LEDGE_GROUP));
+    }
+}
+
+/// Pushing straight into a wall isn't stuck, so a step only counts when part of
+/// the desired movement runs along a contact (it should have slid) and still
+/// almost nothing moved. The nudge goes along that contact.
+#[allow(clippy::type_complexity)]
+fn unstick_controllers(
+    mut controllers: Query<
+        (
+            &mut KinematicCharacterController,
+            &KinematicCharacterControllerOutput,
+            &mut AntiStuck,
+        ),
+        Without<Noclip>,
+    >,
+) {
+    for (mut controller, output, mut anti_stuck) in &mut controllers {
+        let desired = output.desired_translation;
+        let slide = output
+            .collisions
+            .iter()
+            .filter_map(|collision| collision.hit.details)
+            .map(|details| desired - details.normal1 * desired.dot(details.normal1))
+            .find(|tangent| tangent.length() > desired.length() * 0.3);
+
+        let progress = output.effective_translation.length();
+        let Some(slide) = slide.filter(|_| progress < desired.length() * anti_stuck.min_progress)
+        else {
+            anti_stuck.stuck_steps = 0;
+            continue;
+        };
+
+        anti_stuck.stuck_steps += 1;
+        if anti_stuck.stuck_steps >= anti_stuck.max_stuck_steps {
+            let nudge = slide.normalize() * anti_stuck.nudge;
+            controller.translation = Some(controller.translation.unwrap_or_default() + nudge);
+            anti_stuck.stuck_steps = 0;
+        }
+    }
+}
+
+/// Movement, animation and the camera run on virtual time, and the fixed
+/// timestep (and with it Rapier) accumulates from virtual time, so scaling it
+/// slows everything down together without shrinking the physics step.
+fn apply_time_scale(time_scale: Res<TimeScale>, mut time: ResMut<Time<Virtual>>) {
+    if time_scale.is_changed() {
+        time.set_relative_speed(time_scale.0.max(0.0));
+    }
+}
+
+fn adjust_render_scale(
+    mut resolution: ResMut<DynamicResolution>,
+    diagnostics: Res<DiagnosticsStore>,
+    time: Res<Time<Real>>,
+    mut timer: Local<Timer>,
+) {
+    if timer.duration().is_zero() {
+        *timer = Timer::from_seconds(RENDER_SCALE_INTERVAL_SECS, TimerMode::Repeating);
+    }
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(frame_ms) = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+    else {
+        return;
+    };
+
+    let scale = resolution.next_scale(frame_ms as f32);
+    if scale != resolution.scale {
+        info!("Render scale {scale:.1} at {frame_ms:.1}ms per frame");
+        resolution.scale = scale;
+    }
+}
+
+/// The main camera shows a fixed area of the world sized to the window, so
+/// changing the image's resolution only makes the world blurrier or sharper.
+#[allow(clippy::type_complexity)]
+fn resize_scene_target(
+    window: Query<&Window, With<PrimaryWindow>>,
+    resolution: Res<DynamicResolution>,
+    mut images: ResMut<Assets<Image>>,
+    mut presenter: Query<(&Handle<Image>, &mut Sprite), With<ScenePresenter>>,
+    mut camera: Query<&mut OrthographicProjection, With<MainCameraTag>>,
+) {
+    // Minimised windows have no size to match
+    let Some(window) = window
+        .get_single()
+        .ok()
+        .filter(|window| window.physical_width() > 0 && window.physical_height() > 0)
+    else {
+        return;
+    };
+    let (Ok((target, mut sprite)), Ok(mut projection)) =
+        (presenter.get_single_mut(), camera.get_single_mut())
+    else {
+        return;
+    };
+    let logical = window.size();
+    let size = (window.physical_size().as_vec2() * resolution.scale)
+        .round()
+        .as_uvec2()
+        .max(UVec2::ONE);
+    let Some(image) = images.get(target) else {
+        return;
+    };
+    if image.size() == size && sprite.custom_size == Some(logical) {
+        return;
+    }
+
+    if image.size() != size {
+        images.get_mut(target).unwrap().resize(Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        });
+    }
+    sprite.custom_size = Some(logical);
+    projection.scaling_mode = ScalingMode::Fixed {
+        width: logical.x,
+        height: logical.y,
+    };
+}
+
+/// Text is re-rasterised at the new scale rather than stretched, so it stays
+/// crisp at any size.
+fn scale_ui_to_window(
+    mut resized: EventReader<WindowResized>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<UiScaleSettings>,
+    mut ui_scale: ResMut<UiScale>,
+    mut initialized: Local<bool>,
+) {
+    if resized.read().last().is_none() && *initialized {
+        return;
+    }
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    *initialized = true;
+    ui_scale.0 = settings.scale_for(window.size());
+}
+
+/// Radial dead-zone for an analog stick. Magnitudes past the dead-zone are rescaled
+/// to start from zero so movement doesn't jump when leaving it.
+fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let magnitude = stick.length();
+    if magnitude <= dead_zone {
+        return Vec2::ZERO;
+    }
+
+    let scaled = ((magnitude - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick / magnitude * scaled
+}
+
+/// Lerp factor for easing towards a target at `rate` per second.
+/// Unlike `rate * delta`, the result doesn't depend on how time is split into steps.
+fn smoothing_factor(rate: f32, delta_seconds: f32) -> f32 {
+    1.0 - (-rate * delta_seconds).exp()
+}
+
+impl CameraEasing {
+    /// Moves `position` one step towards `target`. Springs are integrated with
+    /// semi-implicit Euler, which stays stable at the frame rates we run at.
+    fn step(self, position: Vec2, target: Vec2, velocity: &mut Vec2, rate: Vec2, dt: f32) -> Vec2 {
+        let next = match self {
+            CameraEasing::Linear { speed } => position.move_towards(target, speed * dt),
+            CameraEasing::Exponential => {
+                let t = Vec2::new(smoothing_factor(rate.x, dt), smoothing_factor(rate.y, dt));
+                position + (target - position) * t
+            }
+            CameraEasing::EaseInOut => {
+                return Self::spring(position, target, velocity, rate * rate, 2.0 * rate, dt)
+            }
+            CameraEasing::Spring { stiffness, damping } => {
+                return Self::spring(
+                    position,
+                    target,
+                    velocity,
+                    Vec2::splat(stiffness),
+                    Vec2::splat(damping),
+                    dt,
+                )
+            }
+        };
+        // Lets a switch to a spring carry on at the same speed
+        if dt > 0.0 {
+            *velocity = (next - position) / dt;
+        }
+        next
+    }
+
+    fn spring(
+        position: Vec2,
+        target: Vec2,
+        velocity: &mut Vec2,
+        stiffness: Vec2,
+        damping: Vec2,
+        dt: f32,
+    ) -> Vec2 {
+        *velocity += ((target - position) * stiffness - *velocity * damping) * dt;
+        position + *velocity * dt
+    }
+}
+
+fn reload_animation_layouts(
+    mut events: EventReader<AssetEvent<AnimationLayout>>,
+    game_assets: Res<GameAssets>,
+    layouts: Res<Assets<AnimationLayout>>,
+    mut players: Query<(&mut AnimationInd, &mut AnimationEvents), With<PlayerTag>>,
+) {
+    for event in events.read() {
+        if !event.is_modified(&game_assets.player_animations) {
+            continue;
+        }
+        let Some(layout) = layouts.get(&game_assets.player_animations) else {
+            continue;
+        };
+        for (mut indices, mut frame_events) in &mut players {
+            *indices = layout.indices();
+            *frame_events = layout.events();
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn animate_sprites(
+    time: Res<Time>,
+    mut sprites: Query<
+        (
+            Entity,
+            &AnimationInd,
+            &mut AnimationTimer,
+            &mut TextureAtlas,
+            &MoveSettings,
+            &FaceDirection,
+            Option<&mut Boredom>,
+            Option<&mut WalkTransition>,
+            Option<&mut AnimationWatchdog>,
+            Option<&AnimationEvents>,
+        ),
+        Without<Frozen>,
+    >,
+    mut frame_events: EventWriter<AnimFrameEvent>,
+) {
+    for (
+        entity,
+        indices,
+        mut timer,
+        mut atlas,
+        move_settings,
+        face_direction,
+        mut boredom,
+        mut transition,
+        watchdog,
+        tags,
+    ) in &mut sprites
+    {
+        let walking = move_settings.is_walking;
+        // Without a bored clip there's nothing to get bored with
+        let mut boredom = boredom.as_deref_mut().filter(|_| indices.bored.is_some());
+        if let Some(boredom) = boredom.as_deref_mut() {
+            boredom.update(walking, time.delta_seconds());
+        }
+        if let Some(transition) = transition.as_deref_mut() {
+            transition.update(walking, indices);
+        }
+        // The layout may have been reloaded without the clip that was playing
+        let transition_clip = transition
+            .as_deref()
+            .and_then(|transition| transition.playing)
+            .and_then(|kind| Some((kind, indices.clip(kind)?)));
+        let (clip_kind, clip) = match (transition_clip, walking, &indices.bored, &boredom) {
+            (Some(transition), ..) => transition,
+            (None, true, ..) => (AnimClip::Walk, &indices.walk),
+            (None, false, Some(bored), Some(boredom)) if boredom.playing => {
+                (AnimClip::Bored, bored)
+            }
+            _ => (AnimClip::Idle, &indices.idle),
+        };
+        let limit = clip_kind.is_one_shot().then(|| clip.max_state_time());
+        let stuck = watchdog
+            .is_some_and(|mut watchdog| watchdog.update(clip_kind, limit, time.delta_seconds()));
+        if stuck {
+            warn!("{clip_kind:?} on {entity} ran past its max_state_time, forcing it to hand over");
+        }
+        timer.set_duration(Duration::from_secs_f32(clip.frame_time));
+        timer.tick(time.delta());
+        if timer.just_finished() || stuck {
+            let start = clip.start(&face_direction.0);
+            // Switching clip or facing restarts from the clip's first frame
+            let next = match atlas.index.checked_sub(start) {
+                Some(frame) if frame < clip.frames => frame + 1,
+                _ => 0,
+            };
+            // One-shot clips hand over to the loop they lead into
+            if next == clip.frames || stuck {
+                let handed_over = match clip_kind {
+                    AnimClip::Bored => boredom.map(|boredom| boredom.playing = false),
+                    AnimClip::StartWalk | AnimClip::StopWalk => transition
+                        .as_deref_mut()
+                        .map(|transition| transition.playing = None),
+                    AnimClip::Walk | AnimClip::Idle => None,
+                };
+                if handed_over.is_some() {
+                    let next_clip = if walking {
+                        &indices.walk
+                    } else {
+                        &indices.idle
+                    };
+                    atlas.index = next_clip.start(&face_direction.0);
+                    continue;
+                }
+            }
+            let frame = next % clip.frames;
+            atlas.index = start + frame;
+
+            let tags = tags.iter().flat_map(|tags| &tags.0);
+            for tag in tags.filter(|tag| tag.clip == clip_kind && tag.frame == frame) {
+                frame_events.send(AnimFrameEvent {
+                    entity,
+                    tag: tag.tag.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn frame_event_sfx(
+    mut frame_events: EventReader<AnimFrameEvent>,
+    transforms: Query<&GlobalTransform>,
+    mut sfx: EventWriter<SpatialSfxEvent>,
+) {
+    for event in frame_events.read() {
+        let Ok(transform) = transforms.get(event.entity) else {
+            continue;
+        };
+        if event.tag == "footstep" {
+            sfx.send(SpatialSfxEvent {
+                position: transform.translation().truncate(),
+                kind: SfxKind::Footstep,
+            });
+        }
+    }
+}
+
+/// The faction tint is the sprite's base colour. Effect tints (hurt flash,
+/// status) should multiply on top of it and put it back when they end, using
+/// FactionTints::tint rather than assuming white.
+fn apply_faction_tints(
+    mut sprites: Query<(&Faction, &mut Sprite), Changed<Faction>>,
+    tints: Res<FactionTints>,
+) {
+    for (faction, mut sprite) in &mut sprites {
+        sprite.color = tints.tint(*faction);
+    }
+}
+
+// Closest interactable the player is within range of
+fn nearest_interactable<'a>(
+    player_pos: Vec2,
+    interactables: impl Iterator<Item = (Entity, &'a Transform, &'a Interactable)>,
+) -> Option<Entity> {
+    let in_range = interactables.filter_map(|(entity, transform, interactable)| {
+        let position = transform.translation.truncate();
+        (position.distance(player_pos) <= interactable.range).then_some((entity, position))
+    });
+    nearest_to(player_pos, in_range).map(|(entity, _)| entity)
+}
+
+/// Closest of `candidates` to `from`, and how far it is. Equally close ones go
+/// to the lowest entity, so the pick doesn't flicker with query order.
+fn nearest_to(
+    from: Vec2,
+    candidates: impl Iterator<Item = (Entity, Vec2)>,
+) -> Option<(Entity, f32)> {
+    candidates
+        .map(|(entity, position)| (entity, position.distance(from)))
+        .min_by(|(a, a_dist), (b, b_dist)| a_dist.total_cmp(b_dist).then(a.cmp(b)))
+}
+
+// Copy of `mesh` grown by `factor` around its origin, with the same UVs
+fn scaled_mesh(mesh: &Mesh, factor: Vec2) -> Mesh {
+    let mut mesh = mesh.clone();
+    if let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    {
+        for position in positions {
+            position[0] *= factor.x;
+            position[1] *= factor.y;
+        }
+    }
+    mesh
+}
+
+/// The outline is drawn around the outside of the sprite, so the outlined mesh
+/// is swapped for one padded by the outline thickness on each side. Only a
+/// shader that fails to load is caught, a compile error in its pipeline isn't
+/// visible from here and leaves the highlighted sprite undrawn.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn highlight_interactables(
+    mut commands: Commands,
+    player: Query<&Transform, With<PlayerTag>>,
+    interactables: Query<(
+        Entity,
+        &Transform,
+        &Interactable,
+        Option<&Handle<ColorMaterial>>,
+        Option<&Mesh2dHandle>,
+        Option<&Highlighted>,
+    )>,
+    color_materials: Res<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut outline_materials: ResMut<Assets<OutlineMaterial>>,
+    settings: Res<OutlineSettings>,
+    shader: Res<OutlineShader>,
+    asset_server: Res<AssetServer>,
+    mut shader_failed: Local<bool>,
+) {
+    if !*shader_failed && matches!(asset_server.load_state(&shader.0), LoadState::Failed(_)) {
+        warn!("Outline shader failed to load, interactables won't be highlighted");
+        *shader_failed = true;
+    }
+
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player_pos = player.translation.truncate();
+    let nearest = nearest_interactable(
+        player_pos,
+        interactables
+            .iter()
+            .map(|(entity, transform, interactable, ..)| (entity, transform, interactable)),
+    )
+    .filter(|_| !*shader_failed);
+
+    for (entity, _, _, material, mesh, highlighted) in &interactables {
+        match (Some(entity) == nearest, material.zip(mesh), highlighted) {
+            (true, Some((material, mesh)), None) => {
+                let (Some(base), Some(padded)) = (
+                    color_materials.get(material),
+                    meshes
+                        .get(&mesh.0)
+                        .map(|mesh| scaled_mesh(mesh, Vec2::splat(1.0 + 2.0 * settings.thickness))),
+                ) else {
+                    continue;
+                };
+                let outline = outline_materials.add(OutlineMaterial {
+                    color: base.color.into(),
+                    outline_color: settings.color.into(),
+                    thickness: settings.thickness,
+                    texture: base.texture.clone(),
+                });
+                // The bounds are recalculated for the padded mesh
+                commands
+                    .entity(entity)
+                    .remove::<(Handle<ColorMaterial>, Aabb)>()
+                    .insert((
+                        outline,
+                        Mesh2dHandle(meshes.add(padded)),
+                        Highlighted {
+                            original: material.clone(),
+                            original_mesh: mesh.0.clone(),
+                        },
+                    ));
+            }
+            (false, _, Some(highlighted)) => {
+                commands
+                    .entity(entity)
+                    .remove::<(Handle<OutlineMaterial>, Highlighted, Aabb)>()
+                    .insert((
+                        highlighted.original.clone(),
+                        Mesh2dHandle(highlighted.original_mesh.clone()),
+                    ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Redraws the lightmap every frame from the max_lights lights closest to the
+/// camera. Each light fades out quadratically to its radius. Overlapping
+/// lights add up, and the lit cells are tinted towards the lights' average
+/// colour.
+fn update_lightmap(
+    camera: Query<&GlobalTransform, With<MainCameraTag>>,
+    lights: Query<(&Light2d, &GlobalTransform)>,
+    lightmap: Query<(&Lightmap, &Handle<Image>)>,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<LightingSettings>,
+    fog_settings: Res<FogSettings>,
+) {
+    let Ok((lightmap, image)) = lightmap.get_single() else {
+        return;
+    };
+    let Some(image) = images.get_mut(image) else {
+        return;
+    };
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let camera_pos = camera.translation().truncate();
+    let mut nearby: Vec<_> = lights
+        .iter()
+        .map(|(light, transform)| (light, transform.translation().truncate()))
+        .collect();
+    nearby.sort_by(|(_, a), (_, b)| {
+        a.distance_squared(camera_pos)
+            .total_cmp(&b.distance_squared(camera_pos))
+    });
+    nearby.truncate(settings.max_lights);
+
+    let cells = lightmap.cells;
+    let mut brightness = vec![0.0; (cells.x * cells.y) as usize];
+    let mut tint = vec![Vec3::ZERO; brightness.len()];
+    // Rows run top to bottom like the image
+    let top_left = fog_settings.extent * Vec2::new(-0.5, 0.5);
+    let to_cell = |position: Vec2| {
+        ((position - top_left) * Vec2::new(1.0, -1.0) / settings.cell_size)
+            .floor()
+            .clamp(Vec2::ZERO, cells.as_vec2())
+            .as_uvec2()
+    };
+    for (light, position) in nearby {
+        let color = Vec3::from_slice(&light.color.to_srgba().to_f32_array_no_alpha());
+        let min = to_cell(position + Vec2::new(-light.radius, light.radius));
+        let max = to_cell(position + Vec2::new(light.radius, -light.radius));
+        for y in min.y..(max.y + 1).min(cells.y) {
+            for x in min.x..(max.x + 1).min(cells.x) {
+                let i = (y * cells.x + x) as usize;
+                let center =
+                    top_left + Vec2::new(x as f32 + 0.5, -(y as f32 + 0.5)) * settings.cell_size;
+                let falloff = (1.0 - center.distance(position) / light.radius).max(0.0);
+                let amount = falloff * falloff * light.intensity;
+                brightness[i] += amount;
+                tint[i] += color * amount;
+            }
+        }
+    }
+
+    for (i, (brightness, tint)) in brightness.into_iter().zip(tint).enumerate() {
+        let lit = brightness.min(1.0);
+        let color = if brightness > 0.0 {
+            tint / brightness * lit
+        } else {
+            Vec3::ZERO
+        };
+        let alpha = settings.darkness * (1.0 - lit);
+        let [r, g, b] = color.to_array().map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+        image.data[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, (alpha * 255.0) as u8]);
+    }
+}
+
+/// The player counts as hidden while their position is inside the bounds of
+/// something drawn above them. Each occluder fades on its own, so any number
+/// can overlap. Meshes fade through their material, which shouldn't be shared.
+#[allow(clippy::type_complexity)]
+fn fade_occluders(
+    player: Query<&GlobalTransform, With<PlayerTag>>,
+    mut occluders: Query<
+        (
+            &Aabb,
+            &GlobalTransform,
+            Option<&mut Sprite>,
+            Option<&Handle<ColorMaterial>>,
+        ),
+        With<FadeWhenOccluding>,
+    >,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    settings: Res<OcclusionFadeSettings>,
+    time: Res<Time>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player = player.translation();
+    let t = smoothing_factor(settings.rate, time.delta_seconds());
+    for (bounds, transform, sprite, material) in &mut occluders {
+        let local = transform.affine().inverse().transform_point3(player);
+        let offset = (local - Vec3::from(bounds.center)).truncate().abs();
+        let hiding = transform.translation().z > player.z
+            && offset
+                .cmple(Vec3::from(bounds.half_extents).truncate())
+                .all();
+        let target = if hiding { settings.faded_alpha } else { 1.0 };
+
+        // Only touch the alpha while it's moving, so materials aren't re-uploaded every frame
+        let fade = |alpha: f32| {
+            let next = alpha.lerp(target, t);
+            (alpha != target).then_some(if (next - target).abs() < 0.001 {
+                target
+            } else {
+                next
+            })
+        };
+        if let Some(mut sprite) = sprite {
+            if let Some(alpha) = fade(sprite.color.alpha()) {
+                sprite.color.set_alpha(alpha);
+            }
+        } else if let Some(handle) = material {
+            let alpha = materials
+                .get(handle)
+                .and_then(|material| fade(material.color.alpha()));
+            if let (Some(alpha), Some(material)) = (alpha, materials.get_mut(handle)) {
+                material.color.set_alpha(alpha);
+            }
+        }
+    }
+}
+
+/// Rewrites the whole mask every frame, cells that just left the vision radius
+/// fall back to the explored alpha.
+fn reveal_fog(
+    player: Query<&Transform, With<PlayerTag>>,
+    mut fog: Query<(&mut FogOfWar, &Handle<Image>)>,
+    mut images: ResMut<Assets<Image>>,
+    settings: Res<FogSettings>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player_pos = player.translation.truncate();
+    for (mut fog, image) in &mut fog {
+        let Some(image) = images.get_mut(image) else {
+            continue;
+        };
+        let cells = fog.cells;
+        for y in 0..cells.y {
+            for x in 0..cells.x {
+                let i = (y * cells.x + x) as usize;
+                let center = settings.cell_center(UVec2::new(x, y));
+                let visible = center.distance(player_pos) <= settings.vision_radius;
+                fog.explored[i] |= visible;
+
+                let alpha = match (visible, fog.explored[i]) {
+                    (true, _) => 0.0,
+                    (false, true) => settings.explored_alpha,
+                    (false, false) => 1.0,
+                };
+                image.data[i * 4 + 3] = (alpha * 255.0) as u8;
+            }
+        }
+    }
+}
+
+fn cursor_world_position(
+    window: &Window,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    // The camera renders at the scene target's resolution, not the window's
+    let to_viewport = camera.logical_viewport_size()? / window.size();
+    camera.viewport_to_world_2d(camera_transform, window.cursor_position()? * to_viewport)
+}
+
+/// Among overlapping hovered entities the topmost wins, then the one whose
+/// centre is closest to the cursor.
+fn show_tooltips(
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCameraTag>>,
+    rapier_context: Res<RapierContext>,
+    tooltips: Query<(&Tooltip, &GlobalTransform)>,
+    mut tooltip_text: Query<(&mut Text, &mut Style, &mut Visibility), With<TooltipText>>,
+    ui_scale: Res<UiScale>,
+) {
+    let (mut text, mut style, mut visibility) = tooltip_text.single_mut();
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Some((cursor, point)) = window.get_single().ok().and_then(|window| {
+        let cursor = window.cursor_position()?;
+        Some((
+            cursor,
+            cursor_world_position(window, camera, camera_transform)?,
+        ))
+    }) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    let has_tooltip = |entity| tooltips.contains(entity);
+    let filter = QueryFilter::new().predicate(&has_tooltip);
+    let mut hovered: Option<(&Tooltip, f32, f32)> = None;
+    rapier_context.intersections_with_point(point, filter, |entity| {
+        let Ok((tooltip, transform)) = tooltips.get(entity) else {
+            return true;
+        };
+        let z = transform.translation().z;
+        let dist = transform.translation().truncate().distance(point);
+        let better = hovered
+            .is_none_or(|(_, best_z, best_dist)| z > best_z || (z == best_z && dist < best_dist));
+        if better {
+            hovered = Some((tooltip, z, dist));
+        }
+        true
+    });
+
+    let Some((tooltip, ..)) = hovered else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    text.sections[0].value.clone_from(&tooltip.text);
+    // UI Px values get multiplied by the UI scale, the cursor position doesn't
+    let position = cursor / ui_scale.0 + TOOLTIP_OFFSET;
+    style.left = Val::Px(position.x);
+    style.top = Val::Px(position.y);
+    *visibility = Visibility::Inherited;
+}
+
+// E or the gamepad's south button, talks to signs and advances dialogue
+fn interact_pressed(
+    keyboard: &ButtonInput<KeyCode>,
+    gamepad_buttons: &ButtonInput<GamepadButton>,
+    gamepads: &Gamepads,
+) -> bool {
+    keyboard.just_pressed(KeyCode::KeyE)
+        || gamepads.iter().any(|gamepad| {
+            gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_signs(
+    mut commands: Commands,
+    player: Query<&Transform, With<PlayerTag>>,
+    signs: Query<(Entity, &Transform, &Interactable, &Sign)>,
+    mut contexts: ResMut<InputContexts>,
+    settings: Res<DialogueSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+) {
+    if !contexts.is_active(InputContext::Gameplay)
+        || !interact_pressed(&keyboard, &gamepad_buttons, &gamepads)
+    {
+        return;
+    }
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let player_pos = player.translation.truncate();
+    let nearest = nearest_interactable(
+        player_pos,
+        signs
+            .iter()
+            .map(|(entity, transform, interactable, _)| (entity, transform, interactable)),
+    );
+    let Some((.., sign)) = nearest
+        .and_then(|entity| signs.get(entity).ok())
+        .filter(|(.., sign)| !sign.lines.is_empty())
+    else {
+        return;
+    };
+
+    contexts.push(InputContext::Dialogue);
+    commands.spawn((
+        TextBundle::from_section("", TextStyle::default())
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(HUD_MARGIN),
+                left: Val::Px(HUD_MARGIN),
+                right: Val::Px(HUD_MARGIN),
+                padding: UiRect::all(Val::Px(HUD_MARGIN)),
+                ..default()
+            })
+            .with_background_color(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+        Dialogue {
+            lines: sign.lines.clone(),
+            chars_per_sec: settings.chars_per_sec,
+            line: 0,
+            revealed: 0.0,
+        },
+    ));
+}
+
+/// Interacting mid-line shows the rest of it, interacting on a finished line
+/// moves to the next. After the last line the box closes and gameplay input
+/// comes back. Runs on real time so it works while the game is paused.
+#[allow(clippy::too_many_arguments)]
+fn advance_dialogue(
+    mut commands: Commands,
+    mut dialogues: Query<(Entity, &mut Dialogue, &mut Text)>,
+    mut contexts: ResMut<InputContexts>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    time: Res<Time<Real>>,
+    mut sfx: EventWriter<SfxEvent>,
+) {
+    let pressed = interact_pressed(&keyboard, &gamepad_buttons, &gamepads);
+    if pressed && !dialogues.is_empty() {
+        sfx.send(SfxEvent {
+            kind: SfxKind::UiSelect,
+        });
+    }
+    for (entity, mut dialogue, mut text) in &mut dialogues {
+        let line_len = dialogue
+            .lines
+            .get(dialogue.line)
+            .map_or(0, |line| line.chars().count());
+        let finished = dialogue.revealed >= line_len as f32;
+
+        if pressed && finished {
+            dialogue.line += 1;
+            dialogue.revealed = 0.0;
+            if dialogue.line >= dialogue.lines.len() {
+                commands.entity(entity).despawn_recursive();
+                contexts.pop();
+                continue;
+            }
+        } else if pressed {
+            dialogue.revealed = line_len as f32;
+        } else {
+            dialogue.revealed = (dialogue.revealed + dialogue.chars_per_sec * time.delta_seconds())
+                .min(line_len as f32);
+        }
+
+        let shown = dialogue.revealed as usize;
+        text.sections[0].value = dialogue.lines[dialogue.line].chars().take(shown).collect();
+    }
+}
+
+/// Particles are UI nodes placed in percent of the window, so they stay put
+/// on screen regardless of the camera. Hidden particles are reused before new
+/// ones are spawned, up to MAX_WEATHER_PARTICLES.
+fn spawn_weather(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    mut particles: Query<(
+        &mut WeatherParticle,
+        &mut Style,
+        &mut BackgroundColor,
+        &mut Visibility,
+    )>,
+    time: Res<Time>,
+    mut pending: Local<f32>,
+) {
+    let Some((rate, fall_speed, size, color)) = weather.kind.particles() else {
+        return;
+    };
+    *pending += rate * weather.intensity.clamp(0.0, 1.0) * time.delta_seconds();
+
+    let mut count = particles.iter().len();
+    let mut hidden = particles
+        .iter_mut()
+        .filter(|(.., visibility)| **visibility == Visibility::Hidden);
+    // Spread along the top edge, wide enough that wind doesn't leave a gap
+    let mut spread = time.elapsed_seconds() * 7919.0;
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+        spread = (spread * 1.618).fract();
+        let x = spread * (1.0 + weather.wind.abs()) - weather.wind.max(0.0);
+        let velocity = Vec2::new(weather.wind, fall_speed);
+
+        if let Some((mut particle, mut style, mut background, mut visibility)) = hidden.next() {
+            particle.velocity = velocity;
+            style.left = Val::Percent(x * 100.0);
+            style.top = Val::Percent(0.0);
+            style.width = Val::Px(size.x);
+            style.height = Val::Px(size.y);
+            background.0 = color;
+            *visibility = Visibility::Inherited;
+        } else if count < MAX_WEATHER_PARTICLES {
+            count += 1;
+            commands.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Percent(x * 100.0),
+                        top: Val::Percent(0.0),
+                        width: Val::Px(size.x),
+                        height: Val::Px(size.y),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    // Behind the HUD
+                    z_index: ZIndex::Global(-1),
+                    ..default()
+                },
+                WeatherParticle { velocity },
+            ));
+        }
+    }
+}
+
+fn move_weather(
+    mut particles: Query<(&WeatherParticle, &mut Style, &mut Visibility)>,
+    time: Res<Time>,
+) {
+    for (particle, mut style, mut visibility) in &mut particles {
+        let (Val::Percent(left), Val::Percent(top)) = (style.left, style.top) else {
+            continue;
+        };
+        let moved = Vec2::new(left, top) + particle.velocity * 100.0 * time.delta_seconds();
+        style.left = Val::Percent(moved.x);
+        style.top = Val::Percent(moved.y);
+        if moved.y > 100.0 {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// Shows the saving hint once the player first walks, and the interact hint
+/// each time they come into range of something, outside of dialogue.
+fn contextual_hints(
+    mut hints: ResMut<HintQueue>,
+    mut flags: ResMut<WorldFlags>,
+    player: Query<(&MoveSettings, &Transform), With<PlayerTag>>,
+    interactables: Query<(Entity, &Transform, &Interactable)>,
+    contexts: Res<InputContexts>,
+    mut in_range: Local<Option<Entity>>,
+) {
+    let Ok((move_settings, transform)) = player.get_single() else {
+        return;
+    };
+    if move_settings.is_walking && !flags.is_set("hint_saving") {
+        flags.set("hint_saving");
+        hints.show_hint(
+            "{pick_slot} pick a save slot, {save} saves to it, {load} loads it",
+            4.0,
+        );
+    }
+
+    let nearest = nearest_interactable(transform.translation.truncate(), interactables.iter());
+    if nearest.is_some() && nearest != *in_range && contexts.is_active(InputContext::Gameplay) {
+        hints.show_hint("{interact} to interact", 2.0);
+    }
+    *in_range = nearest;
+}
+
+/// Any key press switches to the keyboard, any gamepad button or stick
+/// movement past the dead-zone to the gamepad.
+fn track_input_device(
+    mut device: ResMut<LastInputDevice>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    input_settings: Res<InputSettings>,
+) {
+    let stick_moved = gamepads.iter().any(|gamepad| {
+        [
+            (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+        ]
+        .into_iter()
+        .any(|(x, y)| {
+            let axis = |axis_type| {
+                gamepad_axes
+                    .get(GamepadAxis::new(gamepad, axis_type))
+                    .unwrap_or(0.0)
+            };
+            let stick = Vec2::new(axis(x), axis(y));
+            apply_dead_zone(stick, input_settings.stick_dead_zone) != Vec2::ZERO
+        })
+    });
+
+    let used = if keyboard.get_just_pressed().next().is_some() {
+        InputDevice::Keyboard
+    } else if stick_moved || gamepad_buttons.get_just_pressed().next().is_some() {
+        InputDevice::Gamepad
+    } else {
+        return;
+    };
+    // update_hint_text watches for changes, so only switches should count
+    device.set_if_neq(LastInputDevice(used));
+}
+
+// Runs on real time so hints keep fading while the game is slowed or paused
+fn update_hint_text(
+    mut hints: ResMut<HintQueue>,
+    mut hint_text: Query<&mut Text, With<HintText>>,
+    device: Res<LastInputDevice>,
+    time: Res<Time<Real>>,
+) {
+    let section = &mut hint_text.single_mut().sections[0];
+
+    if let Some((text, timer)) = hints.current.as_mut() {
+        timer.tick(time.delta());
+        if !timer.finished() {
+            if device.is_changed() {
+                section.value = fill_prompts(text, device.0);
+            }
+            let alpha = (timer.remaining_secs() / HINT_FADE_SECS).min(1.0);
+            section.style.color.set_alpha(alpha);
+            return;
+        }
+        hints.current = None;
+        section.value.clear();
+    }
+
+    if let Some((text, duration)) = hints.pending.pop_front() {
+        section.value = fill_prompts(&text, device.0);
+        section.style.color.set_alpha(1.0);
+        hints.current = Some((text, Timer::from_seconds(duration, TimerMode::Once)));
+    }
+}
+
+fn spawn_level_boxes(
+    mut commands: Commands,
+    boxes: Query<(Entity, &LevelBox), Added<LevelBox>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, level_box) in &boxes {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(Rectangle::from_size(level_box.size))),
+            materials.add(Color::from(WHITE)),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+            RigidBody::Fixed,
+            Collider::cuboid(level_box.size.x / 2., level_box.size.y / 2.),
+        ));
+    }
+}
+
+fn spawn_ledges(mut commands: Commands, ledges: Query<Entity, Added<OneWayLedge>>) {
+    for entity in &ledges {
+        commands
+            .entity(entity)
+            .insert(CollisionGroups::new(LEDGE_GROUP, Group::ALL));
+    }
+}
+
+fn spawn_crates(
+    mut commands: Commands,
+    crates: Query<(Entity, &LevelCrate), Added<LevelCrate>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    shake_settings: Res<ImpactShakeSettings>,
+) {
+    for (entity, level_crate) in &crates {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(Rectangle::from_size(level_crate.size))),
+            materials.add(level_crate.preset.color()),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+            RigidBody::Dynamic,
+            Collider::cuboid(level_crate.size.x / 2., level_crate.size.y / 2.),
+            LockedAxes::ROTATION_LOCKED,
+            level_crate.preset.physics(),
+            ActiveEvents::CONTACT_FORCE_EVENTS,
+            ContactForceEventThreshold(shake_settings.min_force),
+            Tooltip {
+                text: format!("{:?} crate", level_crate.preset),
+            },
+        ));
+    }
+}
+
+/// Lights placed in the level are torches, shown as a small flame.
+#[allow(clippy::type_complexity)]
+fn spawn_torches(
+    mut commands: Commands,
+    torches: Query<(Entity, &Light2d), (Added<Light2d>, With<LevelEntity>)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, light) in &torches {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(Circle::new(WORLD_SCALE.texels(2.0)))),
+            materials.add(light.color),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+        ));
+    }
+}
+
+/// Sprites that aren't loaded yet are tried again next frame.
+#[allow(clippy::type_complexity)]
+fn generate_sprite_colliders(
+    mut commands: Commands,
+    sprites: Query<(Entity, &Handle<Image>, &ColliderFromSprite), Without<TextureAtlas>>,
+    images: Res<Assets<Image>>,
+    mut cache: ResMut<SpriteColliders>,
+) {
+    for (entity, handle, from_sprite) in &sprites {
+        let Some(image) = images.get(handle) else {
+            continue;
+        };
+        let collider = cache
+            .0
+            .entry((handle.id(), from_sprite.alpha_threshold))
+            .or_insert_with(|| collider_from_image(image, from_sprite.alpha_threshold))
+            .clone();
+
+        let mut entity = commands.entity(entity);
+        entity.remove::<ColliderFromSprite>();
+        if let Some(collider) = collider {
+            entity.insert(collider);
+        }
+    }
+}
+
+/// Traces the outline of the largest opaque shape in the image and decomposes
+/// it into convex parts. Points are in texels around the image centre, like a
+/// sprite's. Holes inside the shape are filled in. Returns None for fully
+/// transparent or non-RGBA8 images.
+///
+/// Edges run between opaque pixels and transparent ones or the image border,
+/// and are chained into closed loops. Points that barely bend the outline are
+/// then dropped, flattest first, so a staircase of pixels becomes one sloped
+/// edge while square corners stay.
+fn collider_from_image(image: &Image, alpha_threshold: u8) -> Option<Collider> {
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) {
+        warn!(
+            "Can't trace a collider from a {:?} image",
+            image.texture_descriptor.format
+        );
+        return None;
+    }
+    let size = image.size().as_ivec2();
+    // Cells count up from the bottom, image rows count down from the top
+    let solid = |cell: IVec2| {
+        cell.cmpge(IVec2::ZERO).all()
+            && cell.cmplt(size).all()
+            && image.data[((size.y - 1 - cell.y) * size.x + cell.x) as usize * 4 + 3]
+                >= alpha_threshold
+    };
+
+    // Counter-clockwise around each solid cell, keeping the solid side on the left
+    let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let cell = IVec2::new(x, y);
+            if !solid(cell) {
+                continue;
+            }
+            let corners = [cell, cell + IVec2::X, cell + IVec2::ONE, cell + IVec2::Y];
+            for (i, neighbour) in [IVec2::NEG_Y, IVec2::X, IVec2::Y, IVec2::NEG_X]
+                .into_iter()
+                .enumerate()
+            {
+                if !solid(cell + neighbour) {
+                    edges
+                        .entry(corners[i])
+                        .or_default()
+                        .push(corners[(i + 1) % 4]);
+                }
+            }
+        }
+    }
+
+    let mut outline: Vec<IVec2> = Vec::new();
+    // Starting from the lowest corner keeps the trace the same from run to run
+    while let Some(&start) = edges.keys().min_by_key(|corner| (corner.y, corner.x)) {
+        let mut outline_loop = vec![start];
+        let mut corner = start;
+        while let Some(next) = edges.get_mut(&corner).and_then(Vec::pop) {
+            if edges[&corner].is_empty() {
+                edges.remove(&corner);
+            }
+            corner = next;
+            if corner == start {
+                break;
+            }
+            outline_loop.push(corner);
+        }
+        if outline_loop.len() > outline.len() {
+            outline = outline_loop;
+        }
+    }
+
+    let centre = size.as_vec2() / 2.0;
+    let mut points: Vec<Vec2> = outline
+        .iter()
+        .map(|corner| corner.as_vec2() - centre)
+        .collect();
+    loop {
+        let n = points.len();
+        let flattest = (0..n)
+            .map(|i| {
+                let (prev, point, next) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+                let along = (next - prev).normalize_or_zero();
+                (i, (point - prev).perp_dot(along).abs())
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        match flattest {
+            Some((i, bend)) if n > 3 && bend < COLLIDER_TRACE_TOLERANCE => {
+                points.remove(i);
+            }
+            _ => break,
+        }
+    }
+    if points.len() < 3 {
+        return None;
+    }
+
+    let n = points.len() as u32;
+    let indices: Vec<[u32; 2]> = (0..n).map(|i| [i, (i + 1) % n]).collect();
+    Some(Collider::convex_decomposition(&points, &indices))
+}
+
+fn spawn_water_zones(
+    mut commands: Commands,
+    zones: Query<(Entity, &WaterZone), Added<WaterZone>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, zone) in &zones {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(Rectangle::from_size(zone.size))),
+            materials.add(Color::srgba(0.2, 0.45, 0.9, 0.6)),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+            Collider::cuboid(zone.size.x / 2., zone.size.y / 2.),
+            Sensor,
+            Tooltip {
+                text: format!("Water, {}% speed", (zone.speed_mult * 100.0).round()),
+            },
+            // The player is kinematic and the zone fixed, a pair Rapier skips by default
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        ));
+    }
+}
+
+fn spawn_tall_grass(
+    mut commands: Commands,
+    zones: Query<(Entity, &TallGrass), Added<TallGrass>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, grass) in &zones {
+        commands.entity(entity).insert((
+            Mesh2dHandle(meshes.add(Rectangle::from_size(grass.size))),
+            materials.add(Color::srgb(0.3, 0.55, 0.2)),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+            Collider::cuboid(grass.size.x / 2., grass.size.y / 2.),
+            Sensor,
+            Tooltip {
+                text: format!("Tall grass, {}% speed", (grass.speed_mult * 100.0).round()),
+            },
+            ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        ));
+    }
+}
+
+fn spawn_ripples(
+    mut commands: Commands,
+    walkers: Query<(&Transform, &MoveSettings, &TerrainEffects), Without<Frozen>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut ripple_mesh: Local<Option<Handle<Mesh>>>,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+) {
+    if timer.duration().is_zero() {
+        *timer = Timer::from_seconds(RIPPLE_INTERVAL_SECS, TimerMode::Repeating);
+    }
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let mesh = ripple_mesh
+        .get_or_insert_with(|| meshes.add(Annulus::new(5.0, 6.0)))
+        .clone();
+    for (transform, move_settings, terrain) in &walkers {
+        if !terrain.in_water || !move_settings.is_walking {
+            continue;
+        }
+
+        let feet = transform.translation.truncate() - Vec2::Y * RIPPLE_FEET_OFFSET;
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh.clone().into(),
+                material: materials.add(Color::srgba(1.0, 1.0, 1.0, 0.8)),
+                transform: Transform::from_translation(feet.extend(-0.5)),
+                ..default()
+            },
+            Ripple,
+            Lifetime::from_seconds(0.6),
+        ));
+    }
+}
+
+/// Kicks up a few leaves and plays a rustle for everything walking through grass.
+#[allow(clippy::too_many_arguments)]
+fn spawn_rustles(
+    mut commands: Commands,
+    walkers: Query<(&Transform, &MoveSettings, &TerrainEffects), Without<Frozen>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut leaf_mesh: Local<Option<Handle<Mesh>>>,
+    mut timer: Local<Timer>,
+    time: Res<Time>,
+    mut sfx: EventWriter<SpatialSfxEvent>,
+) {
+    if timer.duration().is_zero() {
+        *timer = Timer::from_seconds(RUSTLE_INTERVAL_SECS, TimerMode::Repeating);
+    }
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let mesh = leaf_mesh
+        .get_or_insert_with(|| meshes.add(Rectangle::from_length(WORLD_SCALE.texels(1.0))))
+        .clone();
+    for (transform, move_settings, terrain) in &walkers {
+        if !terrain.in_grass || !move_settings.is_walking {
+            continue;
+        }
+
+        let feet = transform.translation.truncate() - Vec2::Y * RIPPLE_FEET_OFFSET;
+        sfx.send(SpatialSfxEvent {
+            position: feet,
+            kind: SfxKind::Rustle,
+        });
+        for spread in [-1.0, 0.0, 1.0] {
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: mesh.clone().into(),
+                    material: materials.add(Color::srgb(0.45, 0.75, 0.3)),
+                    transform: Transform::from_translation(
+                        (feet + Vec2::X * spread * 6.0).extend(1.5),
+                    ),
+                    ..default()
+                },
+                Rustle {
+                    velocity: Vec2::new(spread * 20.0, 40.0),
+                },
+                Lifetime::from_seconds(0.5),
+                Tween::new(
+                    TweenProperty::Alpha { from: 1.0, to: 0.0 },
+                    0.5,
+                    TweenEasing::Linear,
+                ),
+            ));
+        }
+    }
+}
+
+fn animate_ripples(
+    mut ripples: Query<(&Lifetime, &mut Transform, &Handle<ColorMaterial>), With<Ripple>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (lifetime, mut transform, material) in &mut ripples {
+        let progress = lifetime.fraction();
+        transform.scale = Vec3::splat(1.0 + progress * 2.0);
+        if let Some(material) = materials.get_mut(material) {
+            material.color.set_alpha(0.8 * (1.0 - progress));
+        }
+    }
+}
+
+// Leaves fade out with a Tween
+fn animate_rustles(mut leaves: Query<(&Rustle, &mut Transform)>, time: Res<Time>) {
+    for (rustle, mut transform) in &mut leaves {
+        transform.translation += (rustle.velocity * time.delta_seconds()).extend(0.0);
+    }
+}
+
+/// Sprites have their alpha set directly, meshes through their material, which
+/// should belong to the entity alone.
+#[allow(clippy::type_complexity)]
+fn run_tweens(
+    mut commands: Commands,
+    mut tweens: Query<(
+        Entity,
+        &mut Tween,
+        Option<&mut Transform>,
+        Option<&mut Sprite>,
+        Option<&Handle<ColorMaterial>>,
+    )>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    time: Res<Time>,
+    mut completed: EventWriter<TweenCompleted>,
+) {
+    for (entity, mut tween, transform, sprite, material) in &mut tweens {
+        let (t, finished) = tween.advance(time.delta_seconds());
+        if finished {
+            completed.send(TweenCompleted { entity });
+            if tween.repeat == TweenRepeat::Once {
+                commands.entity(entity).remove::<Tween>();
+            }
+        }
+
+        match tween.property {
+            TweenProperty::Translation { from, to } => {
+                if let Some(mut transform) = transform {
+                    transform.translation = from.lerp(to, t);
+                }
+            }
+            TweenProperty::Scale { from, to } => {
+                if let Some(mut transform) = transform {
+                    transform.scale = from.lerp(to, t);
+                }
+            }
+            TweenProperty::Alpha { from, to } => {
+                let alpha = from.lerp(to, t);
+                if let Some(mut sprite) = sprite {
+                    sprite.color.set_alpha(alpha);
+                } else if let Some(material) = material.and_then(|handle| materials.get_mut(handle))
+                {
+                    material.color.set_alpha(alpha);
+                }
+            }
+        }
+    }
+}
+
+/// Only the first step of a contact shakes, a crate resting or sliding against
+/// a wall keeps reporting forces and would otherwise shake for as long as it touches.
+fn shake_on_impacts(
+    mut impacts: EventReader<ContactForceEvent>,
+    mut shakes: EventWriter<ShakeEvent>,
+    settings: Res<ImpactShakeSettings>,
+    mut touching: Local<HashSet<(Entity, Entity)>>,
+) {
+    let previous = std::mem::take(&mut *touching);
+    for impact in impacts.read() {
+        let pair = (impact.collider1, impact.collider2);
+        touching.insert(pair);
+        if !previous.contains(&pair) {
+            shakes.send(ShakeEvent {
+                intensity: impact.max_force_magnitude * settings.pixels_per_force,
+            });
+        }
+    }
+}
+
+/// Identical kinds sent in the same frame only play once, so a burst of
+/// events doesn't stack into one very loud sound. Spatial ones play as loud
+/// as their closest source.
+fn play_sfx(
+    mut commands: Commands,
+    mut events: EventReader<SfxEvent>,
+    mut spatial_events: EventReader<SpatialSfxEvent>,
+    listener: Query<&GlobalTransform, With<MainCameraTag>>,
+    library: Res<SfxLibrary>,
+    settings: Res<SpatialAudioSettings>,
+    volumes: Res<AudioVolumes>,
+) {
+    let mut kinds: HashMap<SfxKind, f32> = events.read().map(|event| (event.kind, 1.0)).collect();
+    let Ok(listener) = listener.get_single() else {
+        return;
+    };
+    let listener = listener.translation().truncate();
+    for event in spatial_events.read() {
+        let Some(volume) = settings.volume_at(event.position.distance(listener)) else {
+            continue;
+        };
+        let loudest = kinds.entry(event.kind).or_insert(0.0);
+        *loudest = loudest.max(volume);
+    }
+
+    for (kind, scale) in kinds {
+        let Some((source, volume)) = library.0.get(&kind) else {
+            continue;
+        };
+        let volume = volume * scale;
+        let bus = kind.bus();
+        commands.spawn((
+            AudioBundle {
+                source: source.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new(volume * volumes.gain(bus))),
+            },
+            BusVolume { bus, volume },
+        ));
+    }
+}
+
+fn start_music(mut commands: Commands, game_assets: Res<GameAssets>, volumes: Res<AudioVolumes>) {
+    commands.spawn((
+        AudioBundle {
+            source: game_assets.music.clone(),
+            settings: PlaybackSettings::LOOP
+                .with_volume(Volume::new(MUSIC_VOLUME * volumes.gain(AudioBus::Music))),
+        },
+        BusVolume {
+            bus: AudioBus::Music,
+            volume: MUSIC_VOLUME,
+        },
+    ));
+}
+
+/// Sounds that are already playing, looping ones included, follow changes to
+/// AudioVolumes. New sinks are caught too, in case the volumes changed between
+/// spawning a sound and it starting. Generic over the sink so tests can run it
+/// without an audio device.
+fn apply_bus_volumes<S: AudioSinkPlayback + Component>(
+    volumes: Res<AudioVolumes>,
+    sinks: Query<(&BusVolume, Ref<S>)>,
+) {
+    for (bus_volume, sink) in &sinks {
+        if volumes.is_changed() || sink.is_added() {
+            sink.set_volume(bus_volume.volume * volumes.gain(bus_volume.bus));
+        }
+    }
+}
+
+// Run condition for a system gated on one of the DebugFlags
+fn debug_flag(flag: fn(&DebugFlags) -> bool) -> impl Fn(Res<DebugFlags>) -> bool {
+    move |flags| flag(&flags)
+}
+
+fn toggle_debug_flags(
+    mut commands: Commands,
+    mut flags: ResMut<DebugFlags>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    player: Query<(Entity, Has<Frozen>), With<PlayerTag>>,
+) {
+    if keyboard.just_pressed(KeyCode::F4) {
+        if let Ok((player, frozen)) = player.get_single() {
+            if frozen {
+                commands.unfreeze(player);
+            } else {
+                commands.freeze(player);
+            }
+        }
+    }
+    if keyboard.just_pressed(KeyCode::F6) {
+        flags.controllers = !flags.controllers;
+    }
+    if keyboard.just_pressed(KeyCode::F7) {
+        flags.velocities = !flags.velocities;
+    }
+    if keyboard.just_pressed(KeyCode::F8) {
+        flags.interact_ranges = !flags.interact_ranges;
+    }
+}
+
+/// Turning noclip off inside a wall moves the player to the nearest free spot,
+/// searched for in growing rings around where they are.
+fn toggle_noclip(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    rapier_context: Res<RapierContext>,
+    mut player: Query<(Entity, &mut Transform, &Collider, Has<Noclip>), With<PlayerTag>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F10) {
+        return;
+    }
+    let Ok((entity, mut transform, collider, noclip)) = player.get_single_mut() else {
+        return;
+    };
+    if !noclip {
+        commands.entity(entity).insert((Noclip, ColliderDisabled));
+        return;
+    }
+    commands
+        .entity(entity)
+        .remove::<(Noclip, ColliderDisabled)>();
+
+    let filter = QueryFilter::new()
+        .exclude_sensors()
+        .exclude_collider(entity);
+    let position = transform.translation.truncate();
+    let step = WORLD_SCALE.texels(2.0);
+    let free = (0..64)
+        .flat_map(|ring| {
+            let directions = if ring == 0 { 1 } else { 16 };
+            (0..directions).map(move |i| {
+                let angle = i as f32 / directions as f32 * std::f32::consts::TAU;
+                position + Vec2::from_angle(angle) * step * ring as f32
+            })
+        })
+        .find(|spot| {
+            rapier_context
+                .intersection_with_shape(*spot, 0.0, collider, filter)
+                .is_none()
+        });
+    match free {
+        Some(spot) => transform.translation = spot.extend(transform.translation.z),
+        None => warn!("No free spot near {position} to leave noclip"),
+    }
+}
+
+/// Enemies walk out from a grid north of the level, projectiles fly out from
+/// the middle of it and leaves scatter around the origin. None of them is a
+/// LevelEntity, so autosaves during a run don't pick them up.
+fn spawn_benchmark_load(
+    mut commands: Commands,
+    benchmark: Res<Benchmark>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // Golden angle steps spread things evenly without a random source
+    let direction = |i: u32| Vec2::from_angle(i as f32 * 2.4);
+
+    let size = WORLD_SCALE.texels(10.0);
+    let columns = (benchmark.enemies as f32).sqrt().ceil().max(1.0) as u32;
+    let origin = Vec2::new(-(columns as f32) * size, 400.0);
+    for i in 0..benchmark.enemies {
+        let cell = Vec2::new((i % columns) as f32, (i / columns) as f32);
+        commands.spawn((
+            BenchmarkEnemy,
+            TransformBundle::from_transform(Transform::from_translation(
+                (origin + cell * size * 2.0).extend(0.0),
+            )),
+            RigidBody::KinematicPositionBased,
+            Collider::cuboid(size / 2.0, size / 2.0),
+            KinematicCharacterController::default(),
+            Velocity(direction(i) * 60.0),
+        ));
+    }
+
+    let center = origin + Vec2::splat(columns as f32 * size);
+    for i in 0..benchmark.projectiles {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_translation(center.extend(0.0))),
+            RigidBody::KinematicVelocityBased,
+            Collider::ball(WORLD_SCALE.texels(1.0)),
+            Sensor,
+            ActiveEvents::COLLISION_EVENTS,
+            bevy_rapier2d::prelude::Velocity::linear(direction(i) * 300.0),
+        ));
+    }
+
+    let mesh = meshes.add(Rectangle::from_length(WORLD_SCALE.texels(1.0)));
+    for i in 0..benchmark.particles {
+        let distance = (i as f32).sqrt() * 8.0;
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh.clone().into(),
+                material: materials.add(Color::srgb(0.45, 0.75, 0.3)),
+                transform: Transform::from_translation((direction(i) * distance).extend(1.5)),
+                ..default()
+            },
+            Rustle {
+                velocity: direction(i) * 10.0,
+            },
+            Tween::new(
+                TweenProperty::Alpha { from: 1.0, to: 0.2 },
+                1.0,
+                TweenEasing::EaseInOut,
+            )
+            .repeating(TweenRepeat::PingPong),
+        ));
+    }
+    info!(
+        "Benchmarking {} frames with {} enemies, {} projectiles and {} particles",
+        benchmark.frames, benchmark.enemies, benchmark.projectiles, benchmark.particles
+    );
+}
+
+fn turn_benchmark_enemies(
+    mut enemies: Query<(&KinematicCharacterControllerOutput, &mut Velocity), With<BenchmarkEnemy>>,
+) {
+    for (output, mut velocity) in &mut enemies {
+        if !output.collisions.is_empty() {
+            velocity.0 = -velocity.0;
+        }
+    }
+}
+
+/// Spawned crates are level entities like the placed ones, so they get their
+/// collider and mesh from spawn_crates and end up in saves.
+fn debug_spawn_at_cursor(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCameraTag>>,
+) {
+    let Some(preset) = [
+        (KeyCode::Digit1, CratePreset::Heavy),
+        (KeyCode::Digit2, CratePreset::Slippery),
+        (KeyCode::Digit3, CratePreset::Bouncy),
+    ]
+    .into_iter()
+    .find_map(|(key, preset)| keyboard.just_pressed(key).then_some(preset)) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Some(point) = window
+        .get_single()
+        .ok()
+        .and_then(|window| cursor_world_position(window, camera, camera_transform))
+    else {
+        return;
+    };
+
+    commands.spawn((
+        LevelEntity,
+        LevelCrate {
+            size: Vec2::splat(WORLD_SCALE.texels(10.0)),
+            preset,
+        },
+        Transform::from_translation(point.extend(0.0)),
+    ));
+}
+
+/// Doesn't check for walls at the cursor, noclip can get the player back out.
+fn debug_teleport_to_cursor(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCameraTag>>,
+    player: Query<Entity, With<PlayerTag>>,
+    mut teleports: EventWriter<TeleportEvent>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+    let Ok((camera, camera_transform)) = camera.get_single() else {
+        return;
+    };
+    let Some(point) = window
+        .get_single()
+        .ok()
+        .and_then(|window| cursor_world_position(window, camera, camera_transform))
+    else {
+        return;
+    };
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    teleports.send(TeleportEvent {
+        entity: player,
+        to: point,
+    });
+}
+
+/// Frames the player with the nearest crate, standing in for a boss until
+/// there are any. Pressing F again goes back to following CameraTarget.
+fn debug_frame_pair(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    camera: Query<(Entity, Has<FramePair>), With<MainCameraTag>>,
+    player: Query<(Entity, &Transform), With<PlayerTag>>,
+    crates: Query<(Entity, &Transform), With<LevelCrate>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    let (Ok((camera, framing)), Ok((player, player_transform))) =
+        (camera.get_single(), player.get_single())
+    else {
+        return;
+    };
+    if framing {
+        commands.entity(camera).remove::<FramePair>();
+        return;
+    }
+
+    let position = player_transform.translation.truncate();
+    let distance = |transform: &Transform| transform.translation.truncate().distance(position);
+    let Some((nearest, _)) = crates
+        .iter()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+    else {
+        return;
+    };
+    commands.entity(camera).insert(FramePair {
+        a: player,
+        b: nearest,
+        padding: 64.0,
+        max_zoom: 2.0,
+    });
+}
+
+/// Draws the shapes ColliderShape can make, anything else is skipped.
+fn draw_controller_gizmos(
+    mut gizmos: Gizmos,
+    controllers: Query<(&Collider, &GlobalTransform), With<KinematicCharacterController>>,
+) {
+    let color = Color::srgb(0.2, 1.0, 0.4);
+    for (collider, transform) in &controllers {
+        let (scale, _, translation) = transform.to_scale_rotation_translation();
+        let center = translation.truncate();
+        if let Some(ball) = collider.as_ball() {
+            gizmos.circle_2d(center, ball.radius() * scale.x, color);
+        } else if let Some(cuboid) = collider.as_cuboid() {
+            let size = cuboid.half_extents() * scale.truncate() * 2.0;
+            gizmos.rect_2d(center, 0.0, size, color);
+        } else if let Some(capsule) = collider.as_capsule() {
+            let radius = capsule.radius() * scale.x;
+            let a = center + capsule.segment().a() * scale.truncate();
+            let b = center + capsule.segment().b() * scale.truncate();
+            gizmos.circle_2d(a, radius, color);
+            gizmos.circle_2d(b, radius, color);
+            gizmos.line_2d(a - Vec2::X * radius, b - Vec2::X * radius, color);
+            gizmos.line_2d(a + Vec2::X * radius, b + Vec2::X * radius, color);
+        }
+    }
+}
+
+// Arrows show a quarter second of movement
+fn draw_velocity_gizmos(mut gizmos: Gizmos, movers: Query<(&Velocity, &GlobalTransform)>) {
+    for (velocity, transform) in &movers {
+        let start = transform.translation().truncate();
+        gizmos.arrow_2d(start, start + velocity.0 * 0.25, Color::srgb(1.0, 0.3, 0.3));
+    }
+}
+
+fn draw_interact_range_gizmos(
+    mut gizmos: Gizmos,
+    interactables: Query<(&Interactable, &GlobalTransform)>,
+) {
+    for (interactable, transform) in &interactables {
+        gizmos.circle_2d(
+            transform.translation().truncate(),
+            interactable.range,
+            Color::from(GOLD),
+        );
+    }
+}
+
+// Average, 95th and 99th percentile and worst of a non-empty list of frame times
+fn frame_stats(frame_ms: &mut [f32]) -> (f32, f32, f32, f32) {
+    frame_ms.sort_by(f32::total_cmp);
+    let percentile = |p: f32| frame_ms[((frame_ms.len() - 1) as f32 * p).round() as usize];
+    let average = frame_ms.iter().sum::<f32>() / frame_ms.len() as f32;
+    (average, percentile(0.95), percentile(0.99), percentile(1.0))
+}
+
+/// Uses real time, so the numbers aren't skewed by the time scale.
+fn log_frame_pacing(mut log: ResMut<FrameLog>, time: Res<Time<Real>>, entities: &Entities) {
+    log.frame_ms.push(time.delta_seconds() * 1000.0);
+    if !log.interval.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let mut frame_ms = std::mem::take(&mut log.frame_ms);
+    let (average, p95, p99, worst) = frame_stats(&mut frame_ms);
+    let count = entities.len();
+    info!(
+        "Frames: {average:.2}ms average, {p95:.2}ms p95, {p99:.2}ms p99, {worst:.2}ms worst \
+         over {} frames, {count} entities",
+        frame_ms.len()
+    );
+
+    let Some(path) = &log.csv_path else {
+        return;
+    };
+    let new_file = !Path::new(path).exists();
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| {
+            if new_file {
+                writeln!(
+                    file,
+                    "elapsed_secs,frames,average_ms,p95_ms,p99_ms,worst_ms,entities"
+                )?;
+            }
+            writeln!(
+                file,
+                "{:.1},{},{average:.3},{p95:.3},{p99:.3},{worst:.3},{count}",
+                time.elapsed_seconds(),
+                frame_ms.len()
+            )
+        });
+    if let Err(err) = written {
+        warn!("Failed to write frame log to {path}: {err}");
+    }
+}
+
+/// Times frames with the wall clock, since game time steps by BENCHMARK_STEP.
+/// The frame the load is spawned in is left out, it's dominated by the spawning.
+fn run_benchmark(
+    mut benchmark: ResMut<Benchmark>,
+    entities: &Entities,
+    mut exit: EventWriter<AppExit>,
+    mut last_frame: Local<Option<Instant>>,
+) {
+    let now = Instant::now();
+    let Some(last) = last_frame.replace(now) else {
+        return;
+    };
+    if benchmark.report.is_some() {
+        return;
+    }
+    benchmark.frame_ms.push((now - last).as_secs_f32() * 1000.0);
+    if benchmark.frame_ms.len() < benchmark.frames.max(1) {
+        return;
+    }
+
+    let (average_ms, p95_ms, p99_ms, worst_ms) = frame_stats(&mut benchmark.frame_ms);
+    let report = BenchmarkReport {
+        frames: benchmark.frame_ms.len(),
+        average_ms,
+        p95_ms,
+        p99_ms,
+        worst_ms,
+        entities: entities.len(),
+    };
+    // Printed rather than logged so it's there whatever RUST_LOG says
+    println!(
+        "Benchmark with {} enemies, {} projectiles and {} particles: {average_ms:.2}ms average, \
+         {p95_ms:.2}ms p95, {p99_ms:.2}ms p99, {worst_ms:.2}ms worst over {} frames, {} entities",
+        benchmark.enemies,
+        benchmark.projectiles,
+        benchmark.particles,
+        report.frames,
+        report.entities
+    );
+    benchmark.report = Some(report);
+    exit.send(AppExit::Success);
+}
+
+/// Warns once when a marker goes over budget, and again only after it has
+/// dropped back under, so a leak doesn't flood the log every frame.
+fn check_entity_budget<T: Component>(
+    budget: Res<EntityBudget>,
+    entities: Query<(), With<T>>,
+    mut over_budget: Local<bool>,
+) {
+    let Some(&limit) = budget.limits.get(&TypeId::of::<T>()) else {
+        return;
+    };
+    let count = entities.iter().count();
+    if count > limit && !*over_budget {
+        warn!(
+            "{count} {} entities, over the budget of {limit}",
+            type_name::<T>()
+        );
+    }
+    *over_budget = count > limit;
+}
+
+fn despawn_expired(
+    mut commands: Commands,
+    mut lifetimes: Query<(Entity, &mut Lifetime)>,
+    time: Res<Time>,
+) {
+    for (entity, mut lifetime) in &mut lifetimes {
+        if lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn count_playtime(mut playtime: ResMut<Playtime>, time: Res<Time>) {
+    playtime.0 += time.delta_seconds();
+}
+
+fn level_scene_keys(world: &mut World) {
+    if !world
+        .resource::<InputContexts>()
+        .is_active(InputContext::Gameplay)
+    {
+        return;
+    }
+    let keyboard = world.resource::<ButtonInput<KeyCode>>();
+    let save = keyboard.just_pressed(KeyCode::F5);
+    let load = keyboard.just_pressed(KeyCode::F9);
+    let picked = [KeyCode::F1, KeyCode::F2, KeyCode::F3]
+        .into_iter()
+        .zip(1..=SAVE_SLOTS)
+        .find(|(key, _)| keyboard.just_pressed(*key))
+        .map(|(_, slot)| slot);
+
+    if let Some(slot) = picked {
+        world.resource_mut::<SaveSlots>().selected = slot;
+        world
+            .resource_mut::<HintQueue>()
+            .show_hint(slot_hint(slot), 2.0);
+    }
+
+    let slot = world.resource::<SaveSlots>().selected;
+    if save {
+        match save_to_slot(world, SAVE_DIR, slot) {
+            Ok(()) => info!("Saved to slot {slot}"),
+            Err(err) => error!("Failed to save to slot {slot}: {err}"),
+        }
+    }
+    if load {
+        match load_from_slot(world, SAVE_DIR, slot) {
+            Ok(true) => info!("Loaded slot {slot}"),
+            Ok(false) => info!("Slot {slot} is empty"),
+            Err(err) => error!("Failed to load slot {slot}: {err}"),
+        }
+    }
+}
+
+// Hint naming the slot and how long its save has been played
+fn slot_hint(slot: u8) -> String {
+    match list_slots(SAVE_DIR)
+        .into_iter()
+        .find(|info| info.slot == slot)
+    {
+        Some(info) => format!(
+            "Slot {slot}: {}m {}s played",
+            info.playtime_secs as u32 / 60,
+            info.playtime_secs as u32 % 60
+        ),
+        None => format!("Slot {slot}: empty"),
+    }
+}
+
+/// Up and down (or the D-pad) step through the slots, wrapping around, and
+/// repeat while held. Runs on real time so it keeps pace while paused.
+#[allow(clippy::too_many_arguments)]
+fn navigate_save_slots(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    contexts: Res<InputContexts>,
+    repeat: Res<NavRepeat>,
+    time: Res<Time<Real>>,
+    mut slots: ResMut<SaveSlots>,
+    mut hints: ResMut<HintQueue>,
+    mut sfx: EventWriter<SfxEvent>,
+    mut state: Local<NavRepeatState>,
+) {
+    let gameplay = contexts.is_active(InputContext::Gameplay);
+    let held = |key, button| {
+        gameplay
+            && (keyboard.pressed(key)
+                || gamepads
+                    .iter()
+                    .any(|gamepad| gamepad_buttons.pressed(GamepadButton::new(gamepad, button))))
+    };
+    let direction = held(KeyCode::ArrowDown, GamepadButtonType::DPadDown) as i32
+        - held(KeyCode::ArrowUp, GamepadButtonType::DPadUp) as i32;
+    let step = state.update(direction, time.delta_seconds(), &repeat);
+    if step == 0 {
+        return;
+    }
+
+    let slot = (slots.selected as i32 - 1 + step).rem_euclid(SAVE_SLOTS as i32) as u8 + 1;
+    slots.selected = slot;
+    hints.show_hint(slot_hint(slot), 2.0);
+    sfx.send(SfxEvent {
+        kind: SfxKind::UiSelect,
+    });
+}
+
+// Scene and metadata file of a save slot in `dir`
+fn slot_paths(dir: &str, slot: u8) -> (String, String) {
+    let name = if slot == AUTOSAVE_SLOT {
+        "autosave".to_string()
+    } else {
+        format!("slot{slot}")
+    };
+    (
+        format!("{dir}/{name}.scn.ron"),
+        format!("{dir}/{name}.meta.ron"),
+    )
+}
+
+/// The level is small enough to save within a frame, so this stays on the main thread.
+fn auto_save(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    let due = world
+        .resource_mut::<AutoSave>()
+        .interval
+        .as_mut()
+        .is_some_and(|interval| interval.tick(delta).just_finished());
+    if !due {
+        return;
+    }
+
+    let dir = world.resource::<AutoSave>().dir.clone();
+    match save_to_slot(world, &dir, AUTOSAVE_SLOT) {
+        Ok(()) => info!("Auto-saved"),
+        Err(err) => error!("Failed to auto-save: {err}"),
+    }
+}
+
+/// The metadata is written last, so a slot only shows up in `list_slots` once
+/// its scene has been saved.
+fn save_to_slot(world: &mut World, dir: &str, slot: u8) -> Result<(), Box<dyn Error>> {
+    let (scene_path, meta_path) = slot_paths(dir, slot);
+    save_scene(world, &scene_path)?;
+
+    let position = world
+        .query_filtered::<&Transform, With<PlayerTag>>()
+        .get_single(world)
+        .map(|transform| transform.translation.truncate())
+        .unwrap_or_default();
+    let info = SlotInfo {
+        slot,
+        playtime_secs: world.resource::<Playtime>().0,
+        position: position.to_array(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    fs::write(
+        meta_path,
+        ron::ser::to_string_pretty(&info, ron::ser::PrettyConfig::default())?,
+    )?;
+    Ok(())
+}
+
+/// Returns false and leaves the world untouched if nothing is saved in the slot.
+fn load_from_slot(world: &mut World, dir: &str, slot: u8) -> Result<bool, Box<dyn Error>> {
+    let (scene_path, meta_path) = slot_paths(dir, slot);
+    if !Path::new(&meta_path).exists() {
+        return Ok(false);
+    }
+
+    let info: SlotInfo = ron::de::from_str(&fs::read_to_string(meta_path)?)?;
+    load_scene(world, &scene_path)?;
+    world.resource_mut::<Playtime>().0 = info.playtime_secs;
+    Ok(true)
+}
+
+/// Metadata of every slot that has a save, in slot order with the auto-save
+/// first. Unreadable slots are logged and left out.
+fn list_slots(dir: &str) -> Vec<SlotInfo> {
+    (AUTOSAVE_SLOT..=SAVE_SLOTS)
+        .filter_map(|slot| {
+            let (_, meta_path) = slot_paths(dir, slot);
+            let data = fs::read_to_string(&meta_path).ok()?;
+            ron::de::from_str(&data)
+                .inspect_err(|err| warn!("Skipping unreadable save {meta_path}: {err}"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Writes every `LevelEntity` to a scene file. Only the components needed to
+/// rebuild the level are kept, the rest is re-added when the level is loaded.
+fn save_scene(world: &mut World, path: &str) -> Result<(), Box<dyn Error>> {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<LevelEntity>>()
+        .iter(world)
+        .collect();
+
+    let scene = DynamicSceneBuilder::from_world(world)
+        .deny_all()
+        .allow::<LevelEntity>()
+        .allow::<LevelBox>()
+        .allow::<LevelCrate>()
+        .allow::<WaterZone>()
+        .allow::<TallGrass>()
+        .allow::<FadeWhenOccluding>()
+        .allow::<OneWayLedge>()
+        .allow::<Transform>()
+        .allow::<ColliderTag>()
+        .allow::<Interactable>()
+        .allow::<Sign>()
+        .allow::<Light2d>()
+        .extract_entities(entities.into_iter())
+        .deny_all_resources()
+        .allow_resource::<Weather>()
+        .allow_resource::<LevelIntro>()
+        .allow_resource::<WorldFlags>()
+        .extract_resources()
+        .build();
+
+    let serialized = scene.serialize(&world.resource::<AppTypeRegistry>().read())?;
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serialized)?;
+    Ok(())
+}
+
+/// Replaces the current level with the one in the scene file. The file is fully
+/// parsed first so an unreadable scene leaves the current level untouched.
+fn load_scene(world: &mut World, path: &str) -> Result<(), Box<dyn Error>> {
+    let data = fs::read_to_string(path)?;
+    let scene = {
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let mut deserializer = ron::de::Deserializer::from_str(&data)?;
+        SceneDeserializer {
+            type_registry: &registry,
+        }
+        .deserialize(&mut deserializer)?
+    };
+
+    let level: Vec<Entity> = world
+        .query_filtered::<Entity, With<LevelEntity>>()
+        .iter(world)
+        .collect();
+    for entity in level {
+        world.entity_mut(entity).despawn_recursive();
+    }
+
+    scene.write_to_world(world, &mut EntityHashMap::default())?;
+    Ok(())
+}
+
+/// Runs before update_camera, so the camera snaps to the new position the same
+/// frame the teleport is applied.
+fn apply_teleports(
+    mut events: EventReader<TeleportEvent>,
+    mut transforms: Query<(&mut Transform, Has<CameraTarget>), Without<MainCameraTag>>,
+    mut camera: Query<&mut CameraValues, With<MainCameraTag>>,
+) {
+    for event in events.read() {
+        let Ok((mut transform, followed)) = transforms.get_mut(event.entity) else {
+            continue;
+        };
+        transform.translation = event.to.extend(transform.translation.z);
+        if let (true, Ok(mut camera)) = (followed, camera.get_single_mut()) {
+            camera.snap = true;
+        }
+    }
+}
+
+/// Runs whenever LevelIntro changes, which covers the level first loading and
+/// loading a save. Takes CameraTarget off the player for the length of the pan
+/// and holds input in the cutscene context.
+fn start_level_intro(
+    mut commands: Commands,
+    intro: Res<LevelIntro>,
+    player: Query<Entity, With<PlayerTag>>,
+    pans: Query<Entity, With<IntroPan>>,
+    mut contexts: ResMut<InputContexts>,
+) {
+    if !intro.is_changed() || intro.waypoints.is_empty() || !pans.is_empty() {
+        return;
+    }
+
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    commands.entity(player).remove::<CameraTarget>();
+    commands.spawn((
+        IntroPan {
+            point: 0,
+            timer: Timer::from_seconds(intro.per_point_time, TimerMode::Repeating),
+        },
+        CameraTarget,
+        TransformBundle::from_transform(Transform::from_translation(
+            intro.waypoints[0].extend(0.0),
+        )),
+    ));
+    contexts.push(InputContext::Cutscene);
+}
+
+/// Interacting skips the rest of the pan.
+#[allow(clippy::too_many_arguments)]
+fn run_level_intro(
+    mut commands: Commands,
+    intro: Res<LevelIntro>,
+    mut pans: Query<(Entity, &mut IntroPan, &mut Transform), Without<PlayerTag>>,
+    player: Query<(Entity, &Transform), With<PlayerTag>>,
+    mut contexts: ResMut<InputContexts>,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+) {
+    let Ok((pan_entity, mut pan, mut transform)) = pans.get_single_mut() else {
+        return;
+    };
+    let Ok((player, player_transform)) = player.get_single() else {
+        return;
+    };
+    let skipped = interact_pressed(&keyboard, &gamepad_buttons, &gamepads);
+    if pan.timer.tick(time.delta()).just_finished() {
+        pan.point += 1;
+    }
+
+    if skipped || pan.point > intro.waypoints.len() {
+        commands.entity(pan_entity).despawn_recursive();
+        commands.entity(player).insert(CameraTarget);
+        if contexts.is_active(InputContext::Cutscene) {
+            contexts.pop();
+        }
+        return;
+    }
+    let target = intro
+        .waypoints
+        .get(pan.point)
+        .copied()
+        .unwrap_or(player_transform.translation.truncate());
+    transform.translation = target.extend(0.0);
+}
+
+/// Peeking uses the right stick, or holding Q peeks the way the player faces.
+/// Letting go eases the peek back to zero.
+///
+/// Moving CameraTarget to another entity pans over with the follow easing. With
+/// no target the camera holds still, with several it picks the lowest entity.
+///
+/// FramePair takes over from CameraTarget while both its entities exist.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_camera(
+    mut camera: Query<
+        (
+            &mut Transform,
+            &mut CameraValues,
+            &mut OrthographicProjection,
+            &ScreenShake,
+            Option<&FramePair>,
+        ),
+        With<MainCameraTag>,
+    >,
+    targets: Query<(Entity, &Transform), (With<CameraTarget>, Without<MainCameraTag>)>,
+    positions: Query<&GlobalTransform>,
+    player: Query<&FaceDirection, With<PlayerTag>>,
+    time: Res<Time>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    input_settings: Res<InputSettings>,
+    contexts: Res<InputContexts>,
+    mut target_count: Local<Option<usize>>,
+) {
+    let (
+        Ok(face_direction),
+        Ok((mut camera_transform, mut camera_val, mut projection, shake, pair)),
+    ) = (player.get_single(), camera.get_single_mut())
+    else {
+        return;
+    };
+    // Follow from where the camera would be without last frame's bob and shake
+    camera_transform.translation -= (camera_val.bob_offset + shake.offset).extend(0.0);
+
+    let count = targets.iter().len();
+    if count != 1 && *target_count != Some(count) {
+        warn!("{count} entities have CameraTarget, expected one");
+    }
+    *target_count = Some(count);
+
+    let mut peek_input = Vec2::ZERO;
+    let gameplay = contexts.is_active(InputContext::Gameplay);
+    if let Some(gamepad) = gamepads.iter().next().filter(|_| gameplay) {
+        let axis = |axis_type| {
+            gamepad_axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        let stick = Vec2::new(
+            axis(GamepadAxisType::RightStickX),
+            axis(GamepadAxisType::RightStickY),
+        );
+        peek_input = apply_dead_zone(stick, input_settings.stick_dead_zone);
+    }
+    if peek_input == Vec2::ZERO && gameplay && keyboard.pressed(KeyCode::KeyQ) {
+        peek_input = face_direction.0.vector();
+    }
+    let peek_target = peek_input * camera_val.peek_reach * projection.area.size();
+    let peek_t = smoothing_factor(camera_val.peek_rate, time.delta_seconds());
+    camera_val.peek_offset = camera_val.peek_offset.lerp(peek_target, peek_t);
+
+    let rotation_t = smoothing_factor(camera_val.rotation_rate, time.delta_seconds());
+    camera_transform.rotation = camera_transform.rotation.slerp(
+        Quat::from_rotation_z(camera_val.target_rotation),
+        rotation_t,
+    );
+
+    // Changing the offset moves the target, the follow lerp below eases into it.
+    // It's along the screen's axes, so it turns with the camera.
+    let screen_offset = camera_val.screen_offset * projection.area.size();
+    let offset = (camera_transform.rotation * screen_offset.extend(0.0)).truncate();
+    let framed = pair.and_then(|pair| {
+        let a = positions.get(pair.a).ok()?.translation().truncate();
+        let b = positions.get(pair.b).ok()?.translation().truncate();
+        Some((pair, a, b))
+    });
+    let followed = match framed {
+        Some((_, a, b)) => a.midpoint(b),
+        None => targets.iter().min_by_key(|(entity, _)| *entity).map_or(
+            camera_transform.translation.truncate() + offset - camera_val.peek_offset,
+            |(_, transform)| transform.translation.truncate(),
+        ),
+    };
+    let goal = followed - offset + camera_val.peek_offset;
+
+    // Zoom needed to fit both with padding, measured against the unzoomed view
+    let zoom = framed.map_or(1.0, |(pair, a, b)| {
+        let view = projection.area.size() / projection.scale;
+        let needed = ((a - b).abs() + Vec2::splat(pair.padding * 2.0)) / view;
+        needed.max_element().clamp(1.0, pair.max_zoom.max(1.0))
+    });
+    let zoom_t = smoothing_factor(camera_val.zoom_rate, time.delta_seconds());
+    projection.scale = projection.scale.lerp(zoom, zoom_t);
+
+    let CameraValues {
+        easing,
+        lerp_factor,
+        ref mut follow_velocity,
+        ref mut snap,
+        ..
+    } = *camera_val;
+    let position = if std::mem::take(snap) {
+        *follow_velocity = Vec2::ZERO;
+        goal
+    } else {
+        easing.step(
+            camera_transform.translation.truncate(),
+            goal,
+            follow_velocity,
+            lerp_factor,
+            time.delta_seconds(),
+        )
+    };
+    camera_transform.translation = position.extend(camera_transform.translation.z);
+}
+
+/// Each layer is sized to a whole number of tile pairs covering the view, even
+/// rotated, and snapped back a tile whenever it drifts one away from the
+/// camera, so it looks endless. Runs after the camera's shake so it moves with it.
+fn scroll_parallax(
+    camera: Query<(&Transform, &OrthographicProjection), With<MainCameraTag>>,
+    mut layers: Query<(&mut Transform, &mut Sprite, &ParallaxLayer), Without<MainCameraTag>>,
+) {
+    let Ok((camera_transform, projection)) = camera.get_single() else {
+        return;
+    };
+    let camera_pos = camera_transform.translation.truncate();
+    let view = projection.area.size().length();
+    for (mut transform, mut sprite, layer) in &mut layers {
+        let pairs = ((Vec2::splat(view) + layer.tile_size * 2.0) / (layer.tile_size * 2.0)).ceil();
+        let size = pairs * layer.tile_size * 2.0;
+        if sprite.custom_size != Some(size) {
+            sprite.custom_size = Some(size);
+        }
+        let drift = (camera_pos * layer.factor).rem_euclid(layer.tile_size);
+        let position = camera_pos - drift;
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+/// Dips twice per walk cycle, on its first and middle frames. Easing
+/// the strength keeps the bob from snapping off when the player stops.
+#[allow(clippy::type_complexity)]
+fn bob_camera(
+    mut camera: Query<(&mut Transform, &mut CameraValues), With<MainCameraTag>>,
+    player: Query<
+        (
+            &AnimationInd,
+            &AnimationTimer,
+            &TextureAtlas,
+            &FaceDirection,
+            &MoveSettings,
+            &Velocity,
+        ),
+        With<PlayerTag>,
+    >,
+    time: Res<Time>,
+    mut dip: Local<f32>,
+) {
+    let (
+        Ok((mut transform, mut camera_val)),
+        Ok((indices, timer, atlas, face_direction, move_settings, velocity)),
+    ) = (camera.get_single_mut(), player.get_single())
+    else {
+        return;
+    };
+
+    let walk = &indices.walk;
+    let target = if move_settings.is_walking {
+        (velocity.0.length() / move_settings.speed).min(1.0)
+    } else {
+        0.0
+    };
+    let t = smoothing_factor(BOB_EASE_RATE, time.delta_seconds());
+    camera_val.bob_strength += (target - camera_val.bob_strength) * t;
+
+    // Keeps the last phase while idle so the bob eases out instead of jumping
+    let frame = atlas
+        .index
+        .checked_sub(walk.start(&face_direction.0))
+        .filter(|frame| *frame < walk.frames);
+    if let Some(frame) = frame {
+        let phase = (frame as f32 + timer.fraction()) / walk.frames as f32;
+        *dip = (phase * 2.0 * std::f32::consts::TAU).cos();
+    }
+    // Down the screen, which turns with the camera
+    let down = (transform.rotation * Vec3::NEG_Y).truncate();
+    camera_val.bob_offset = down * *dip * camera_val.camera_bob * camera_val.bob_strength;
+    transform.translation += camera_val.bob_offset.extend(0.0);
+}
+
+/// Overlapping shakes don't add up, the strongest one wins and decays linearly.
+fn shake_camera(
+    mut camera: Query<(&mut Transform, &mut ScreenShake), With<MainCameraTag>>,
+    mut events: EventReader<ShakeEvent>,
+    time: Res<Time>,
+) {
+    let Ok((mut transform, mut shake)) = camera.get_single_mut() else {
+        return;
+    };
+    for event in events.read() {
+        shake.intensity = shake
+            .intensity
+            .max(event.intensity.min(shake.max_intensity));
+    }
+    shake.intensity = (shake.intensity - shake.decay * time.delta_seconds()).max(0.0);
+
+    let t = time.elapsed_seconds();
+    let jitter = Vec2::new((t * 47.0).sin(), (t * 61.0).cos());
+    shake.offset = jitter * shake.intensity;
+    transform.translation += shake.offset.extend(0.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use bevy::{ecs::system::RunSystemOnce, log::Level};
+
+    use super::*;
+
+    #[test]
+    fn smoothing_factor_is_independent_of_step_rate() {
+        let rate = 5.0;
+        let eased = |hz: u32| {
+            let factor = smoothing_factor(rate, 1.0 / hz as f32);
+            (0..hz).fold(0.0_f32, |value, _| value.lerp(1.0, factor))
+        };
+        let expected = 1.0 - (-rate).exp();
+        for hz in [30, 60, 120] {
+            assert!((eased(hz) - expected).abs() < 1e-4, "{hz} Hz");
+        }
+    }
+
+    #[test]
+    fn scaled_mesh_grows_positions_and_keeps_uvs() {
+        let mesh = Mesh::from(Rectangle::new(10.0, 4.0));
+        let scaled = scaled_mesh(&mesh, Vec2::new(2.0, 0.5));
+        let positions = |mesh: &Mesh| match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+            _ => panic!("expected 3D positions"),
+        };
+        for (before, after) in positions(&mesh).iter().zip(positions(&scaled)) {
+            assert_eq!(after, [before[0] * 2.0, before[1] * 0.5, before[2]]);
+        }
+        assert_eq!(
+            mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+                .map(|uvs| uvs.get_bytes().to_vec()),
+            scaled
+                .attribute(Mesh::ATTRIBUTE_UV_0)
+                .map(|uvs| uvs.get_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn dead_zone_drops_small_input_and_rescales_the_rest() {
+        let dead_zone = 0.2;
+        assert_eq!(apply_dead_zone(Vec2::new(0.1, 0.1), dead_zone), Vec2::ZERO);
+        assert_eq!(apply_dead_zone(Vec2::new(0.2, 0.0), dead_zone), Vec2::ZERO);
+
+        // Just past the edge starts from zero rather than jumping to 0.2
+        let past_edge = apply_dead_zone(Vec2::new(0.21, 0.0), dead_zone);
+        assert!(past_edge.x > 0.0 && past_edge.x < 0.02);
+
+        let halfway = apply_dead_zone(Vec2::new(0.0, -0.6), dead_zone);
+        assert!((halfway - Vec2::new(0.0, -0.5)).length() < 1e-6);
+        let full = apply_dead_zone(Vec2::new(1.0, 1.0), dead_zone);
+        assert!((full.length() - 1.0).abs() < 1e-6);
+        assert!((full.x - full.y).abs() < 1e-6);
+    }
+
+    // Empty world with the level's saved types registered, as the app does
+    fn level_world() -> World {
+        let registry = AppTypeRegistry::default();
+        {
+            let mut registry = registry.write();
+            registry.register::<LevelEntity>();
+            registry.register::<LevelBox>();
+            registry.register::<Sign>();
+            registry.register::<Transform>();
+            registry.register::<WorldFlags>();
+        }
+        let mut world = World::new();
+        world.insert_resource(registry);
+        world
+    }
+
+    // Fresh directory under the system temp dir, unique to the test and run
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rob-game-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn level_scene_round_trips() {
+        let dir = temp_dir("scene");
+        let path = dir.join("level.scn.ron");
+        let path = path.to_str().unwrap();
+
+        let mut world = level_world();
+        let mut flags = WorldFlags::default();
+        flags.set("hint_saving");
+        world.insert_resource(flags);
+        world.spawn((
+            LevelEntity,
+            LevelBox {
+                size: Vec2::new(30.0, 10.0),
+            },
+            Transform::from_xyz(200.0, -40.0, 0.0),
+        ));
+        world.spawn((
+            LevelEntity,
+            Sign {
+                lines: vec!["Hello".to_string()],
+            },
+            Transform::from_xyz(-5.0, 5.0, 0.0),
+        ));
+        // Not part of the level, so neither saved nor cleared on load
+        world.spawn(Transform::from_xyz(1.0, 2.0, 3.0));
+        save_scene(&mut world, path).unwrap();
+
+        // Wander off from the saved state before loading it back
+        for mut transform in world
+            .query_filtered::<&mut Transform, With<LevelBox>>()
+            .iter_mut(&mut world)
+        {
+            transform.translation = Vec3::ZERO;
+        }
+        world.resource_mut::<WorldFlags>().0.clear();
+        load_scene(&mut world, path).unwrap();
+
+        let mut level = world.query_filtered::<(), With<LevelEntity>>();
+        assert_eq!(level.iter(&world).count(), 2);
+        let (level_box, transform) = world.query::<(&LevelBox, &Transform)>().single(&world);
+        assert_eq!(level_box.size, Vec2::new(30.0, 10.0));
+        assert_eq!(transform.translation, Vec3::new(200.0, -40.0, 0.0));
+        let sign = world.query::<&Sign>().single(&world);
+        assert_eq!(sign.lines, ["Hello"]);
+        assert!(world.resource::<WorldFlags>().is_set("hint_saving"));
+        let mut others = world.query_filtered::<(), (With<Transform>, Without<LevelEntity>)>();
+        assert_eq!(others.iter(&world).count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn held_axis_falls_back_to_the_key_still_held() {
+        let mut axis = HeldAxis::default();
+        // (A held, D held) each step, A is negative and D positive
+        let steps = [
+            ((true, false), -1.0),
+            ((true, true), 1.0),
+            ((true, false), -1.0),
+            ((false, false), 0.0),
+            ((false, true), 1.0),
+            ((true, true), -1.0),
+            ((false, true), 1.0),
+        ];
+        for (step, ((a, d), expected)) in steps.into_iter().enumerate() {
+            assert_eq!(axis.update(a, d), expected, "step {step}");
+        }
+    }
+
+    #[test]
+    fn nav_repeat_pulses_on_press_then_after_delay_then_at_rate() {
+        let repeat = NavRepeat {
+            initial_delay: 0.4,
+            repeat_rate: 10.0,
+        };
+        let mut state = NavRepeatState::default();
+        let dt = 0.05;
+        // Frame number of each pulse while holding down for a second
+        let pulses: Vec<u32> = (0..20)
+            .filter(|_| state.update(1, dt, &repeat) != 0)
+            .collect();
+        // Immediately, at 0.4s, then every 0.1s after it
+        assert_eq!(pulses, [0, 8, 10, 12, 14, 16, 18]);
+
+        // Letting go and pressing again, or switching direction, pulses at once
+        assert_eq!(state.update(0, dt, &repeat), 0);
+        assert_eq!(state.update(1, dt, &repeat), 1);
+        assert_eq!(state.update(-1, dt, &repeat), -1);
+        assert_eq!(state.update(-1, dt, &repeat), 0);
+    }
+
+    #[test]
+    fn watchdog_fires_once_a_one_shot_overstays() {
+        let mut watchdog = AnimationWatchdog::default();
+        let steps = |watchdog: &mut AnimationWatchdog, clip, limit, count| {
+            (0..count).any(|_| watchdog.update(clip, limit, 0.1))
+        };
+        // Stuck in the bored clip with a one second limit
+        assert!(!steps(&mut watchdog, AnimClip::Bored, Some(1.0), 9));
+        assert!(steps(&mut watchdog, AnimClip::Bored, Some(1.0), 3));
+        // Switching clip starts the clock again
+        assert!(!steps(&mut watchdog, AnimClip::StopWalk, Some(1.0), 9));
+        // Loops have no limit
+        assert!(!steps(&mut watchdog, AnimClip::Idle, None, 100));
+    }
+
+    #[test]
+    fn max_state_time_defaults_to_twice_the_clip() {
+        let mut clip = AnimIndices {
+            left: 0,
+            right: 0,
+            up: 0,
+            down: 0,
+            frames: 4,
+            frame_time: 0.1,
+            max_state_time: None,
+        };
+        assert!((clip.max_state_time() - 0.8).abs() < 1e-6);
+        clip.max_state_time = Some(3.0);
+        assert_eq!(clip.max_state_time(), 3.0);
+    }
+
+    #[test]
+    fn ui_scale_fits_the_window() {
+        let settings = UiScaleSettings {
+            reference_size: Vec2::new(1280.0, 720.0),
+            min_scale: 0.5,
+            max_scale: 2.0,
+        };
+        assert_eq!(settings.scale_for(Vec2::new(1280.0, 720.0)), 1.0);
+        // 4K is three times wider and taller, capped at max_scale
+        assert_eq!(settings.scale_for(Vec2::new(3840.0, 2160.0)), 2.0);
+        // The narrower axis decides, so nothing is cut off
+        assert_eq!(settings.scale_for(Vec2::new(1920.0, 720.0)), 1.0);
+        assert_eq!(settings.scale_for(Vec2::new(200.0, 150.0)), 0.5);
+    }
+
+    #[test]
+    fn input_contexts_keep_gameplay_at_the_bottom() {
+        let mut contexts = InputContexts::default();
+        assert!(contexts.is_active(InputContext::Gameplay));
+
+        contexts.push(InputContext::Dialogue);
+        contexts.push(InputContext::Cutscene);
+        assert!(contexts.is_active(InputContext::Cutscene));
+        assert!(!contexts.is_active(InputContext::Dialogue));
+
+        assert_eq!(contexts.pop(), Some(InputContext::Cutscene));
+        assert!(contexts.is_active(InputContext::Dialogue));
+        assert_eq!(contexts.pop(), Some(InputContext::Dialogue));
+        assert_eq!(contexts.pop(), None);
+        assert!(contexts.is_active(InputContext::Gameplay));
+    }
+
+    #[test]
+    fn slots_list_only_what_was_saved() {
+        let dir = temp_dir("slots");
+        let dir_path = dir.to_str().unwrap();
+        let mut world = level_world();
+        world.init_resource::<WorldFlags>();
+        world.insert_resource(Playtime(65.0));
+        let player = world
+            .spawn((PlayerTag, Transform::from_xyz(10.0, -20.0, 0.0)))
+            .id();
+
+        assert!(list_slots(dir_path).is_empty());
+        assert!(!load_from_slot(&mut world, dir_path, 1).unwrap());
+
+        save_to_slot(&mut world, dir_path, 2).unwrap();
+        let slots = list_slots(dir_path);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].slot, 2);
+        assert_eq!(slots[0].playtime_secs, 65.0);
+        assert_eq!(slots[0].position, [10.0, -20.0]);
+
+        world.resource_mut::<Playtime>().0 = 200.0;
+        world.get_mut::<Transform>(player).unwrap().translation = Vec3::new(-3.0, 4.0, 0.0);
+        save_to_slot(&mut world, dir_path, AUTOSAVE_SLOT).unwrap();
+        save_to_slot(&mut world, dir_path, 3).unwrap();
+        let slots = list_slots(dir_path);
+        let numbers: Vec<u8> = slots.iter().map(|info| info.slot).collect();
+        assert_eq!(numbers, [AUTOSAVE_SLOT, 2, 3]);
+        assert_eq!(slots[2].playtime_secs, 200.0);
+        assert_eq!(slots[2].position, [-3.0, 4.0]);
+
+        assert!(load_from_slot(&mut world, dir_path, 2).unwrap());
+        assert_eq!(world.resource::<Playtime>().0, 65.0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prompts_follow_the_device() {
+        let text = "{move} to walk, {save} to save";
+        assert_eq!(
+            fill_prompts(text, InputDevice::Keyboard),
+            "WASD to walk, F5 to save"
+        );
+        assert_eq!(
+            fill_prompts(text, InputDevice::Gamepad),
+            "Left stick to walk, F5 to save"
+        );
+        assert_eq!(
+            fill_prompts("{interact}", InputDevice::Gamepad),
+            "South button"
+        );
+        assert_eq!(
+            fill_prompts("{unknown}", InputDevice::Keyboard),
+            "{unknown}"
+        );
+    }
+
+    #[test]
+    fn spatial_volume_falls_off_to_silence_at_max_range() {
+        let settings = SpatialAudioSettings {
+            max_range: 100.0,
+            falloff: 2.0,
+        };
+        assert_eq!(settings.volume_at(0.0), Some(1.0));
+        assert_eq!(settings.volume_at(50.0), Some(0.25));
+        assert_eq!(settings.volume_at(100.0), None);
+        assert_eq!(settings.volume_at(150.0), None);
+
+        let linear = SpatialAudioSettings {
+            falloff: 1.0,
+            ..settings
+        };
+        assert_eq!(linear.volume_at(75.0), Some(0.25));
+    }
+
+    #[test]
+    fn nearest_breaks_ties_by_entity_and_respects_range() {
+        let (a, b, c) = (
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        );
+        assert_eq!(nearest_to(Vec2::ZERO, std::iter::empty()), None);
+
+        let tied = [(b, Vec2::new(10.0, 0.0)), (a, Vec2::new(0.0, -10.0))];
+        assert_eq!(nearest_to(Vec2::ZERO, tied.into_iter()), Some((a, 10.0)));
+        assert_eq!(
+            nearest_to(Vec2::ZERO, tied.into_iter().rev()),
+            Some((a, 10.0))
+        );
+
+        let close = (
+            c,
+            Transform::from_xyz(5.0, 0.0, 0.0),
+            Interactable { range: 4.0 },
+        );
+        let at_edge = (
+            b,
+            Transform::from_xyz(20.0, 0.0, 0.0),
+            Interactable { range: 20.0 },
+        );
+        let beyond = (
+            b,
+            Transform::from_xyz(30.0, 0.0, 0.0),
+            Interactable { range: 20.0 },
+        );
+        let pick = |candidates: [&(Entity, Transform, Interactable); 2]| {
+            nearest_interactable(
+                Vec2::ZERO,
+                candidates.into_iter().map(|(e, t, i)| (*e, t, i)),
+            )
+        };
+        // The closest is out of its own range, so the one right at its edge wins
+        assert_eq!(pick([&close, &at_edge]), Some(b));
+        assert_eq!(pick([&close, &beyond]), None);
+    }
+
+    #[test]
+    fn dynamic_resolution_steps_with_hysteresis_and_clamps() {
+        let resolution = |scale| DynamicResolution {
+            target_frame_ms: 16.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            scale,
+        };
+        let mid = resolution(0.75);
+        assert_eq!(mid.next_scale(20.0), 0.75 - RENDER_SCALE_STEP);
+        assert_eq!(mid.next_scale(10.0), 0.75 + RENDER_SCALE_STEP);
+        // Between the two thresholds the scale holds
+        assert_eq!(mid.next_scale(13.0), 0.75);
+        assert_eq!(mid.next_scale(17.0), 0.75);
+
+        assert_eq!(resolution(0.5).next_scale(40.0), 0.5);
+        assert_eq!(resolution(1.0).next_scale(1.0), 1.0);
+    }
+
+    #[test]
+    fn frame_stats_sort_and_pick_percentiles() {
+        // 1 to 100 ms, in no particular order
+        let mut frame_ms: Vec<f32> = (0..100).map(|i| ((i * 37) % 100 + 1) as f32).collect();
+        assert_eq!(frame_stats(&mut frame_ms), (50.5, 95.0, 99.0, 100.0));
+
+        assert_eq!(frame_stats(&mut [7.0]), (7.0, 7.0, 7.0, 7.0));
+    }
+
+    // Image with one row of texels per string, '#' opaque and anything else clear
+    fn alpha_image(rows: &[&str]) -> Image {
+        let data = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .flat_map(|texel| [255, 255, 255, if texel == '#' { 255 } else { 0 }])
+            .collect();
+        Image::new(
+            Extent3d {
+                width: rows[0].len() as u32,
+                height: rows.len() as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        )
+    }
+
+    #[test]
+    fn collider_traces_the_opaque_texels() {
+        let contains = |collider: &Collider, x: f32, y: f32| {
+            collider.contains_point(Vec2::ZERO, 0.0, Vec2::new(x, y))
+        };
+        let clear = alpha_image(&["....", "....", "....", "...."]);
+        assert!(collider_from_image(&clear, 128).is_none());
+
+        let block = alpha_image(&["......", ".####.", ".####.", "......"]);
+        let block = collider_from_image(&block, 128).unwrap();
+        assert!(contains(&block, 0.0, 0.0));
+        assert!(contains(&block, 1.5, -0.5));
+        assert!(!contains(&block, 2.5, 0.0));
+        assert!(!contains(&block, 0.0, 1.5));
+
+        // A notch open to the top edge stays out of the collider
+        let notched = alpha_image(&["##..##", "##..##", "######", "######"]);
+        let notched = collider_from_image(&notched, 128).unwrap();
+        assert!(!contains(&notched, 0.0, 1.0));
+        assert!(contains(&notched, 0.0, -1.0));
+        assert!(contains(&notched, -2.0, 1.0));
+
+        // Only the outer outline is traced, so an enclosed hole is filled in
+        let ring = alpha_image(&["######", "##..##", "##..##", "######"]);
+        let ring = collider_from_image(&ring, 128).unwrap();
+        assert!(contains(&ring, 0.0, 0.0));
+        assert!(!contains(&ring, 3.5, 0.0));
+    }
+
+    #[test]
+    fn tweens_step_through_each_repeat_mode() {
+        let property = TweenProperty::Alpha { from: 0.0, to: 1.0 };
+        let mut once = Tween::new(property, 1.0, TweenEasing::Linear);
+        assert_eq!(once.advance(0.25), (0.25, false));
+        assert_eq!(once.advance(1.0), (1.0, true));
+
+        let mut looping =
+            Tween::new(property, 1.0, TweenEasing::Linear).repeating(TweenRepeat::Loop);
+        assert_eq!(looping.advance(0.5), (0.5, false));
+        assert_eq!(looping.advance(0.75), (0.25, true));
+
+        let mut ping_pong =
+            Tween::new(property, 1.0, TweenEasing::Linear).repeating(TweenRepeat::PingPong);
+        // The leg that ends is finished off before heading back
+        assert_eq!(ping_pong.advance(1.25), (1.0, true));
+        assert_eq!(ping_pong.advance(0.5), (0.25, false));
+        assert_eq!(ping_pong.advance(0.5), (0.0, true));
+        assert_eq!(ping_pong.advance(0.5), (0.75, false));
+
+        let mut eased = Tween::new(property, 1.0, TweenEasing::EaseIn);
+        assert_eq!(eased.advance(0.5), (0.25, false));
+    }
+
+    #[test]
+    fn easings_meet_at_the_ends() {
+        for easing in [
+            TweenEasing::Linear,
+            TweenEasing::EaseIn,
+            TweenEasing::EaseOut,
+            TweenEasing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+        assert_eq!(TweenEasing::EaseOut.apply(0.5), 0.75);
+        assert_eq!(TweenEasing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn bus_gain_is_master_times_bus() {
+        let mut volumes = AudioVolumes {
+            master: 0.5,
+            music: 0.5,
+            sfx: 1.0,
+            ui: 0.0,
+        };
+        assert_eq!(volumes.gain(AudioBus::Music), 0.25);
+        assert_eq!(volumes.gain(AudioBus::Sfx), 0.5);
+        assert_eq!(volumes.gain(AudioBus::Ui), 0.0);
+
+        // Out of range settings are clamped rather than boosting
+        volumes.sfx = 2.0;
+        assert_eq!(volumes.gain(AudioBus::Sfx), 0.5);
+
+        volumes.master = 0.0;
+        for bus in [AudioBus::Music, AudioBus::Sfx, AudioBus::Ui] {
+            assert_eq!(volumes.gain(bus), 0.0);
+        }
+    }
+
+    #[test]
+    fn move_quantization_snaps_to_steps() {
+        let step = 1.0 / 256.0;
+        let value = Vec2::new(0.3, -12.345);
+        let off = MoveQuantization {
+            enabled: false,
+            step,
+        };
+        assert_eq!(off.apply(value), value);
+
+        let on = MoveQuantization {
+            enabled: true,
+            step,
+        };
+        let snapped = on.apply(value);
+        assert_eq!(snapped, Vec2::new(77.0, -3160.0) * step);
+        assert_eq!(on.apply(snapped), snapped);
+        // Anything under half a step rounds away to nothing
+        assert_eq!(on.apply(Vec2::splat(step * 0.4)), Vec2::ZERO);
+    }
+
+    #[test]
+    fn benchmark_counts_come_from_the_variable() {
+        assert!(!Benchmark::parse(None).enabled);
+
+        let defaults = Benchmark::parse(Some(""));
+        assert!(defaults.enabled);
+        assert_eq!(
+            (
+                defaults.frames,
+                defaults.enemies,
+                defaults.projectiles,
+                defaults.particles
+            ),
+            (600, 200, 500, 2000)
+        );
+
+        let given = Benchmark::parse(Some(
+            "frames=120, enemies = 5,projectiles=7,particles=oops,other=1",
+        ));
+        assert_eq!(
+            (
+                given.frames,
+                given.enemies,
+                given.projectiles,
+                given.particles
+            ),
+            (120, 5, 7, 2000)
+        );
+    }
+
+    // World with what update_camera reads and a camera at the origin
+    fn camera_world() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Gamepads>();
+        world.init_resource::<Axis<GamepadAxis>>();
+        world.init_resource::<InputContexts>();
+        world.insert_resource(InputSettings {
+            stick_dead_zone: 0.2,
+        });
+        let camera = world
+            .spawn((
+                MainCameraTag,
+                Transform::default(),
+                OrthographicProjection::default(),
+                CameraValues {
+                    lerp_factor: Vec2::splat(2.0),
+                    easing: CameraEasing::Exponential,
+                    follow_velocity: Vec2::ZERO,
+                    snap: false,
+                    screen_offset: Vec2::ZERO,
+                    peek_reach: 0.3,
+                    peek_rate: 4.0,
+                    peek_offset: Vec2::ZERO,
+                    target_rotation: 0.0,
+                    rotation_rate: 3.0,
+                    zoom_rate: 2.0,
+                    camera_bob: 2.0,
+                    bob_strength: 0.0,
+                    bob_offset: Vec2::ZERO,
+                },
+                ScreenShake {
+                    max_intensity: 12.0,
+                    decay: 40.0,
+                    intensity: 0.0,
+                    offset: Vec2::ZERO,
+                },
+            ))
+            .id();
+        (world, camera)
+    }
+
+    #[test]
+    fn camera_systems_wait_for_a_player() {
+        let (mut world, camera) = camera_world();
+        world.spawn((CameraTarget, Transform::from_xyz(100.0, 0.0, 0.0)));
+        world.run_system_once(update_camera);
+        world.run_system_once(bob_camera);
+        assert_eq!(
+            world.get::<Transform>(camera).unwrap().translation,
+            Vec3::ZERO
+        );
+    }
+
+    #[test]
+    fn outline_swaps_in_within_range_and_back_out() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<ColorMaterial>()
+            .init_asset::<Mesh>()
+            .init_asset::<OutlineMaterial>()
+            .insert_resource(OutlineSettings {
+                color: Color::WHITE,
+                thickness: 0.1,
+            })
+            .insert_resource(OutlineShader(Handle::default()))
+            .add_systems(Update, highlight_interactables);
+
+        let world = app.world_mut();
+        let material = world
+            .resource_mut::<Assets<ColorMaterial>>()
+            .add(ColorMaterial::default());
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Rectangle::new(16.0, 16.0));
+        let player = world.spawn((PlayerTag, Transform::default())).id();
+        let sign = world
+            .spawn((
+                Interactable { range: 20.0 },
+                Transform::from_xyz(10.0, 0.0, 0.0),
+                material.clone(),
+                Mesh2dHandle(mesh.clone()),
+            ))
+            .id();
+
+        app.update();
+        let sign_ref = app.world().entity(sign);
+        assert!(sign_ref.contains::<Handle<OutlineMaterial>>());
+        assert!(!sign_ref.contains::<Handle<ColorMaterial>>());
+        assert_ne!(sign_ref.get::<Mesh2dHandle>().unwrap().0, mesh);
+
+        app.world_mut()
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation
+            .x = 100.0;
+        app.update();
+        let sign_ref = app.world().entity(sign);
+        assert!(!sign_ref.contains::<Handle<OutlineMaterial>>());
+        assert!(!sign_ref.contains::<Highlighted>());
+        assert_eq!(sign_ref.get::<Handle<ColorMaterial>>(), Some(&material));
+        assert_eq!(sign_ref.get::<Mesh2dHandle>().unwrap().0, mesh);
+    }
+
+    #[test]
+    fn ui_scale_follows_window_resizes() {
+        let mut app = App::new();
+        app.add_event::<WindowResized>()
+            .init_resource::<UiScale>()
+            .insert_resource(UiScaleSettings {
+                reference_size: Vec2::new(1280.0, 720.0),
+                min_scale: 0.75,
+                max_scale: 2.0,
+            })
+            .add_systems(Update, scale_ui_to_window);
+        let mut window = Window::default();
+        window.resolution.set(640.0, 360.0);
+        let window = app.world_mut().spawn((window, PrimaryWindow)).id();
+
+        // Picked up on the first frame, before any resize
+        app.update();
+        assert_eq!(app.world().resource::<UiScale>().0, 0.75);
+
+        app.world_mut()
+            .get_mut::<Window>(window)
+            .unwrap()
+            .resolution
+            .set(2560.0, 1440.0);
+        app.world_mut().send_event(WindowResized {
+            window,
+            width: 2560.0,
+            height: 1440.0,
+        });
+        app.update();
+        assert_eq!(app.world().resource::<UiScale>().0, 2.0);
+    }
+
+    // The player's settings from setup
+    fn player_move_settings() -> MoveSettings {
+        MoveSettings {
+            is_walking: false,
+            speed: 320.0,
+            accel: 20.0,
+            fric: 15.0,
+            turn_decel: 40.0,
+            normalize_diagonal: true,
+            stop_speed: 2.0,
+        }
+    }
+
+    #[test]
+    fn player_components_reflect_by_field() {
+        use bevy::reflect::{GetPath, ReflectRef};
+
+        let mut app = App::new();
+        app.register_type::<MoveSettings>()
+            .register_type::<FaceDirection>();
+        let player = app
+            .world_mut()
+            .spawn((
+                PlayerTag,
+                player_move_settings(),
+                FaceDirection(FacingDirection::Left),
+            ))
+            .id();
+
+        let world = app.world();
+        let registry = world.resource::<AppTypeRegistry>().read();
+        let reflect = |type_id| {
+            registry
+                .get_type_data::<ReflectComponent>(type_id)
+                .and_then(|component| component.reflect(world.entity(player)))
+                .unwrap()
+        };
+        let settings = reflect(TypeId::of::<MoveSettings>());
+        assert_eq!(settings.path::<f32>("speed"), Ok(&320.0));
+        let facing = reflect(TypeId::of::<FaceDirection>());
+        let ReflectRef::Enum(facing) = facing.reflect_path(".0").unwrap().reflect_ref() else {
+            panic!("expected FacingDirection to reflect as an enum");
+        };
+        assert_eq!(facing.variant_name(), "Left");
+    }
+
+    #[test]
+    fn turning_round_crosses_zero_sooner_than_accelerating() {
+        let dt = 1.0 / 64.0;
+        let settings = player_move_settings();
+        let frames_to_cross = |settings: &MoveSettings| {
+            let mut velocity = Vec2::new(settings.speed, 0.0);
+            (1..)
+                .find(|_| {
+                    velocity = step_velocity(velocity, Vec2::NEG_X, settings, 1.0, dt);
+                    velocity.x <= 0.0
+                })
+                .unwrap()
+        };
+        let turning = frames_to_cross(&settings);
+        let without_turn_decel = frames_to_cross(&MoveSettings {
+            turn_decel: settings.accel,
+            ..player_move_settings()
+        });
+        assert!(
+            turning < without_turn_decel,
+            "{turning} vs {without_turn_decel}"
+        );
+
+        // Letting go stops with fric, slower than turning round
+        let mut velocity = Vec2::new(settings.speed, 0.0);
+        for _ in 0..turning {
+            velocity = step_velocity(velocity, Vec2::ZERO, &settings, 1.0, dt);
+        }
+        assert!(velocity.x > 0.0);
+    }
+
+    #[test]
+    fn time_scale_slows_fixed_step_movement() {
+        use bevy::time::TimeUpdateStrategy;
+
+        let step = Duration::from_micros(15_625);
+        let distance = |scale: f32| {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+                .insert_resource(TimeUpdateStrategy::ManualDuration(step))
+                .insert_resource(TimeScale(scale))
+                .insert_resource(MoveQuantization {
+                    enabled: false,
+                    step: 1.0,
+                })
+                .add_systems(First, apply_time_scale)
+                .add_systems(FixedUpdate, apply_kinematics);
+            let player = app
+                .world_mut()
+                .spawn((
+                    KinematicCharacterController::default(),
+                    Transform::default(),
+                    Velocity(Vec2::new(64.0, 0.0)),
+                    Noclip,
+                ))
+                .id();
+            for _ in 0..128 {
+                app.update();
+            }
+            app.world().get::<Transform>(player).unwrap().translation.x
+        };
+        // A fixed step at full speed covers one pixel
+        let full = distance(1.0);
+        assert!(full > 100.0);
+        assert!((distance(0.5) - full / 2.0).abs() <= 1.0);
+        assert_eq!(distance(0.0), 0.0);
+    }
+
+    #[test]
+    fn hints_queue_and_clear_after_their_duration() {
+        let mut world = World::new();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<LastInputDevice>();
+        let mut hints = HintQueue::default();
+        hints.show_hint("{interact} to interact", 1.0);
+        hints.show_hint("Second", 1.0);
+        world.insert_resource(hints);
+        let line = world
+            .spawn((HintText, Text::from_section("", TextStyle::default())))
+            .id();
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time<Real>>()
+                .update_with_duration(Duration::from_secs_f32(secs));
+            world.run_system_once(update_hint_text);
+            world.get::<Text>(line).unwrap().sections[0].value.clone()
+        };
+
+        assert_eq!(run_for(&mut world, 0.0), "E to interact");
+        // Still showing, the second waits its turn
+        assert_eq!(run_for(&mut world, 0.5), "E to interact");
+        assert_eq!(run_for(&mut world, 0.6), "Second");
+        assert_eq!(run_for(&mut world, 1.1), "");
+        assert!(world.resource::<HintQueue>().current.is_none());
+    }
+
+    // Rapier at the game's scale, stepping once per update, headless
+    fn physics_app() -> App {
+        use bevy::time::TimeUpdateStrategy;
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            TransformPlugin,
+            HierarchyPlugin,
+            AssetPlugin::default(),
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(WORLD_SCALE.pixels_per_meter()),
+        ))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            1.0 / 60.0,
+        )))
+        .init_asset::<Mesh>()
+        .init_asset::<ColorMaterial>();
+        app
+    }
+
+    #[test]
+    fn water_slows_only_while_inside() {
+        let mut app = physics_app();
+        app.add_event::<SfxEvent>()
+            .add_systems(Update, (spawn_water_zones, update_terrain_effects));
+        app.world_mut().spawn((
+            WaterZone {
+                size: Vec2::new(100.0, 100.0),
+                speed_mult: 0.5,
+            },
+            Transform::default(),
+        ));
+        let player = app
+            .world_mut()
+            .spawn((
+                TerrainEffects {
+                    speed_mult: 1.0,
+                    in_water: false,
+                    in_grass: false,
+                },
+                RigidBody::KinematicPositionBased,
+                Collider::ball(8.0),
+                TransformBundle::from_transform(Transform::from_xyz(20.0, 0.0, 0.0)),
+            ))
+            .id();
+        let terrain = |app: &App| {
+            let terrain = app.world().get::<TerrainEffects>(player).unwrap();
+            (terrain.speed_mult, terrain.in_water)
+        };
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(terrain(&app), (0.5, true));
+
+        app.world_mut()
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation
+            .x = 200.0;
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(terrain(&app), (1.0, false));
+    }
+
+    #[test]
+    fn loading_waits_for_required_assets() {
+        use std::{
+            future::poll_fn,
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc,
+            },
+            task::Poll,
+        };
+
+        use bevy::state::app::StatesPlugin;
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin))
+            .init_asset::<Image>()
+            .init_state::<GameState>()
+            .add_systems(
+                Update,
+                check_assets_loaded.run_if(in_state(GameState::Loading)),
+            );
+        // Stands in for a file, finishes loading once `ready` is set
+        let ready = Arc::new(AtomicBool::new(false));
+        let image = app.world().resource::<AssetServer>().add_async({
+            let ready = ready.clone();
+            poll_fn(move |cx| {
+                if ready.load(Ordering::Relaxed) {
+                    Poll::Ready(Ok::<_, std::io::Error>(Image::default()))
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+        });
+        app.insert_resource(RequiredAssets(vec![image.untyped()]));
+        let state = |app: &App| *app.world().resource::<State<GameState>>().get();
+
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(state(&app), GameState::Loading);
+
+        ready.store(true, Ordering::Relaxed);
+        for _ in 0..100 {
+            app.update();
+            if state(&app) == GameState::Playing {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("still loading after the asset finished");
+    }
+
+    #[test]
+    fn controllers_nudge_along_the_wall_after_sticking() {
+        let mut world = World::new();
+        let wall_hit = CharacterCollision {
+            entity: Entity::PLACEHOLDER,
+            character_translation: Vec2::ZERO,
+            character_rotation: 0.0,
+            translation_applied: Vec2::ZERO,
+            translation_remaining: Vec2::ZERO,
+            hit: ShapeCastHit {
+                time_of_impact: 0.0,
+                details: Some(ShapeCastHitDetails {
+                    witness1: Vec2::ZERO,
+                    witness2: Vec2::ZERO,
+                    normal1: Vec2::X,
+                    normal2: Vec2::NEG_X,
+                }),
+                status: ShapeCastStatus::Converged,
+            },
+        };
+        // Heading up and right into a wall on the right
+        let output = |effective_translation| KinematicCharacterControllerOutput {
+            desired_translation: Vec2::new(1.0, 1.0),
+            effective_translation,
+            collisions: vec![wall_hit],
+            ..default()
+        };
+        let controller = world
+            .spawn((
+                KinematicCharacterController::default(),
+                output(Vec2::ZERO),
+                AntiStuck {
+                    min_progress: 0.05,
+                    max_stuck_steps: 3,
+                    nudge: 1.0,
+                    stuck_steps: 0,
+                },
+            ))
+            .id();
+        let step = |world: &mut World| {
+            world.run_system_once(unstick_controllers);
+            world
+                .get_mut::<KinematicCharacterController>(controller)
+                .unwrap()
+                .translation
+                .take()
+        };
+
+        assert_eq!(step(&mut world), None);
+        assert_eq!(step(&mut world), None);
+        assert_eq!(step(&mut world), Some(Vec2::Y));
+
+        // Sliding up the wall is progress, so the count starts over
+        assert_eq!(step(&mut world), None);
+        *world
+            .get_mut::<KinematicCharacterControllerOutput>(controller)
+            .unwrap() = output(Vec2::new(0.0, 0.9));
+        for _ in 0..5 {
+            assert_eq!(step(&mut world), None);
+        }
+        assert_eq!(world.get::<AntiStuck>(controller).unwrap().stuck_steps, 0);
+    }
+
+    #[test]
+    fn screen_offset_shifts_where_the_player_sits() {
+        let (mut world, camera) = camera_world();
+        world.spawn((
+            PlayerTag,
+            CameraTarget,
+            FaceDirection(FacingDirection::Down),
+            Transform::default(),
+        ));
+        world
+            .get_mut::<OrthographicProjection>(camera)
+            .unwrap()
+            .area = Rect::from_center_size(Vec2::ZERO, Vec2::new(800.0, 600.0));
+        let mut values = world.get_mut::<CameraValues>(camera).unwrap();
+        values.screen_offset = Vec2::new(0.25, 0.0);
+        values.snap = true;
+
+        // A quarter of the view right of centre puts the camera that far left
+        world.run_system_once(update_camera);
+        let x = |world: &World| world.get::<Transform>(camera).unwrap().translation.x;
+        assert_eq!(x(&world), -200.0);
+
+        // Taking it away eases back rather than jumping
+        world.get_mut::<CameraValues>(camera).unwrap().screen_offset = Vec2::ZERO;
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.1));
+        world.run_system_once(update_camera);
+        assert!(x(&world) > -200.0 && x(&world) < 0.0, "{}", x(&world));
+    }
+
+    #[test]
+    fn lifetimes_despawn_once_finished() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        let short = world.spawn(Lifetime::from_seconds(0.5)).id();
+        let long = world.spawn(Lifetime::from_seconds(2.0)).id();
+        let mut run_for = |secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(despawn_expired);
+            (
+                world.get_entity(short).is_some(),
+                world.get_entity(long).is_some(),
+            )
+        };
+        assert_eq!(run_for(0.4), (true, true));
+        assert_eq!(run_for(0.2), (false, true));
+        assert_eq!(run_for(1.5), (false, false));
+    }
+
+    #[test]
+    fn slippery_crates_slide_farther_than_heavy_ones() {
+        let mut app = physics_app();
+        app.insert_resource(ImpactShakeSettings {
+            min_force: 100.0,
+            pixels_per_force: 0.0,
+        })
+        .add_systems(Update, spawn_crates);
+        app.world_mut()
+            .resource_mut::<RapierConfiguration>()
+            .gravity = Vec2::ZERO;
+        let mut spawn = |preset, y| {
+            app.world_mut()
+                .spawn((
+                    LevelCrate {
+                        size: Vec2::splat(20.0),
+                        preset,
+                    },
+                    TransformBundle::from_transform(Transform::from_xyz(0.0, y, 0.0)),
+                ))
+                .id()
+        };
+        let heavy = spawn(CratePreset::Heavy, 0.0);
+        let slippery = spawn(CratePreset::Slippery, 100.0);
+        app.update();
+
+        // The same push for both
+        for entity in [heavy, slippery] {
+            app.world_mut().entity_mut(entity).insert(ExternalImpulse {
+                impulse: Vec2::new(50.0, 0.0),
+                torque_impulse: 0.0,
+            });
+        }
+        for _ in 0..60 {
+            app.update();
+        }
+        let x = |entity| app.world().get::<Transform>(entity).unwrap().translation.x;
+        assert!(x(heavy) > 0.0);
+        assert!(
+            x(slippery) > x(heavy) * 2.0,
+            "{} vs {}",
+            x(slippery),
+            x(heavy)
+        );
+    }
+
+    #[test]
+    fn sfx_events_spawn_one_sound_per_kind() {
+        let mut world = World::new();
+        world.init_resource::<Events<SfxEvent>>();
+        world.init_resource::<Events<SpatialSfxEvent>>();
+        let footstep = Handle::<AudioSource>::weak_from_u128(1);
+        let splash = Handle::<AudioSource>::weak_from_u128(2);
+        world.insert_resource(SfxLibrary(HashMap::from([
+            (SfxKind::Footstep, (footstep.clone(), 0.5)),
+            (SfxKind::Splash, (splash.clone(), 0.8)),
+            (SfxKind::Rustle, (Handle::weak_from_u128(3), 1.0)),
+        ])));
+        world.insert_resource(SpatialAudioSettings {
+            max_range: 100.0,
+            falloff: 1.0,
+        });
+        world.insert_resource(AudioVolumes {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+            ui: 1.0,
+        });
+        world.spawn((MainCameraTag, GlobalTransform::default()));
+
+        for _ in 0..3 {
+            world.send_event(SfxEvent {
+                kind: SfxKind::Footstep,
+            });
+        }
+        world.send_event(SpatialSfxEvent {
+            position: Vec2::new(50.0, 0.0),
+            kind: SfxKind::Splash,
+        });
+        world.send_event(SpatialSfxEvent {
+            position: Vec2::new(500.0, 0.0),
+            kind: SfxKind::Rustle,
+        });
+        world.run_system_once(play_sfx);
+
+        let mut sounds: Vec<_> = world
+            .query::<(&Handle<AudioSource>, &BusVolume)>()
+            .iter(&world)
+            .map(|(source, bus_volume)| (source.clone(), bus_volume.volume))
+            .collect();
+        sounds.sort_by(|a, b| a.1.total_cmp(&b.1));
+        // The splash is halfway to max_range, so at half its volume, and the
+        // rustle is out of range
+        assert_eq!(sounds, [(splash, 0.4), (footstep, 0.5)]);
+    }
+
+    #[test]
+    fn colliders_scale_with_the_sprite_to_world_pixels() {
+        let mut app = physics_app();
+        // Sized in texels on a sprite, and directly in world pixels
+        app.world_mut().spawn((
+            ColliderShape::Ball { radius: 7.0 }.collider(),
+            TransformBundle::from_transform(sprite_transform(Vec3::ZERO, None)),
+        ));
+        let half_size = WORLD_SCALE.texels(5.0);
+        app.world_mut().spawn((
+            Collider::cuboid(half_size, half_size),
+            TransformBundle::from_transform(Transform::from_xyz(100.0, 0.0, 0.0)),
+        ));
+        app.update();
+
+        let context = app.world().resource::<RapierContext>();
+        let hits = |x: f32| {
+            let mut hits = 0;
+            context.intersections_with_point(Vec2::new(x, 0.0), QueryFilter::default(), |_| {
+                hits += 1;
+                true
+            });
+            hits
+        };
+        // A 7 texel radius is 21 pixels
+        assert_eq!((hits(20.5), hits(21.5)), (1, 0));
+        assert_eq!((hits(114.5), hits(115.5)), (1, 0));
+        assert_eq!(
+            context.integration_parameters.length_unit,
+            WORLD_SCALE.texels(WORLD_SCALE.texels_per_meter)
+        );
+    }
+
+    #[test]
+    fn ledges_can_be_dropped_off_but_not_climbed() {
+        // Where a controller starting at `start` ends up after moving `step` a frame
+        let walk = |start: Vec2, step: Vec2| {
+            let mut app = physics_app();
+            app.add_systems(Update, (spawn_ledges, pass_one_way_ledges).chain());
+            let size = Vec2::new(200.0, 12.0);
+            app.world_mut().spawn((
+                OneWayLedge {
+                    direction: Vec2::NEG_Y,
+                },
+                LevelBox { size },
+                Collider::cuboid(size.x / 2.0, size.y / 2.0),
+                TransformBundle::default(),
+            ));
+            let player = app
+                .world_mut()
+                .spawn((
+                    RigidBody::KinematicPositionBased,
+                    Collider::ball(8.0),
+                    KinematicCharacterController::default(),
+                    TransformBundle::from_transform(Transform::from_translation(start.extend(0.0))),
+                ))
+                .id();
+            for _ in 0..20 {
+                app.world_mut()
+                    .get_mut::<KinematicCharacterController>(player)
+                    .unwrap()
+                    .translation = Some(step);
+                app.update();
+            }
+            app.world().get::<Transform>(player).unwrap().translation
+        };
+
+        // Dropping down through it
+        assert!(walk(Vec2::new(0.0, 40.0), Vec2::new(0.0, -4.0)).y < -30.0);
+        // Climbing back up or walking in from the side stops at its edge
+        let climbed = walk(Vec2::new(0.0, -40.0), Vec2::new(0.0, 4.0));
+        assert!(climbed.y < -13.0, "{climbed}");
+        let walked = walk(Vec2::new(-130.0, 0.0), Vec2::new(4.0, 0.0));
+        assert!(walked.x < -107.0, "{walked}");
+    }
+
+    // World after setup has spawned the level, with the assets it needs added
+    // directly rather than loaded
+    fn setup_world() -> World {
+        setup_world_with(PlayerCollider::parse(None))
+    }
+
+    fn setup_world_with(player_collider: PlayerCollider) -> World {
+        let mut world = World::new();
+        let layout: AnimationLayout =
+            ron::de::from_bytes(include_bytes!("../assets/animations/player.anim.ron")).unwrap();
+        let mut layouts = Assets::<AnimationLayout>::default();
+        world.insert_resource(GameAssets {
+            spritesheet: Handle::default(),
+            player_animations: layouts.add(layout),
+            rock: Handle::default(),
+            music: Handle::default(),
+        });
+        world.insert_resource(layouts);
+        world.init_resource::<Assets<TextureAtlasLayout>>();
+        world.init_resource::<Assets<Image>>();
+        world.init_resource::<HintQueue>();
+        world.insert_resource(player_collider);
+        world.insert_resource(FogSettings {
+            extent: Vec2::new(1600.0, 1200.0),
+            cell_size: WORLD_SCALE.texels(4.0),
+            vision_radius: 220.0,
+            explored_alpha: 0.6,
+        });
+        world.insert_resource(LightingSettings {
+            darkness: 0.45,
+            cell_size: WORLD_SCALE.texels(4.0),
+            max_lights: 16,
+        });
+        world.run_system_once(setup);
+        world
+    }
+
+    #[test]
+    fn player_sprite_uses_the_shared_scale() {
+        let mut world = setup_world();
+        let transform = world
+            .query_filtered::<&Transform, With<PlayerTag>>()
+            .single(&world);
+        assert_eq!(transform.scale, Vec3::splat(SPRITE_SCALE));
+        assert_eq!(SPRITE_SCALE, WORLD_SCALE.texel_size);
+    }
+
+    // Runs `f`, counting the events at `level` it logs on this thread
+    fn count_logs<R>(level: Level, f: impl FnOnce() -> R) -> (R, usize) {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use bevy::{
+            log::tracing_subscriber::{
+                layer::{Context, SubscriberExt},
+                Layer, Registry,
+            },
+            utils::tracing::{subscriber, Event, Subscriber},
+        };
+
+        struct CountLogs(Level, Arc<AtomicUsize>);
+        impl<S: Subscriber> Layer<S> for CountLogs {
+            fn on_event(&self, event: &Event, _: Context<S>) {
+                if *event.metadata().level() == self.0 {
+                    self.1.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let subscriber = Registry::default().with(CountLogs(level, count.clone()));
+        let result = subscriber::with_default(subscriber, f);
+        (result, count.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn entity_budget_warns_once_per_overrun() {
+        let mut world = World::new();
+        world.insert_resource(EntityBudget {
+            enabled: true,
+            limits: HashMap::from([(TypeId::of::<Ripple>(), 2)]),
+        });
+        // Kept across runs, it remembers whether it already warned
+        let mut check = IntoSystem::into_system(check_entity_budget::<Ripple>);
+        check.initialize(&mut world);
+        let ripples: Vec<Entity> = (0..3).map(|_| world.spawn(Ripple).id()).collect();
+        let mut frames = |world: &mut World, count| {
+            let (_, warnings) = count_logs(Level::WARN, || {
+                for _ in 0..count {
+                    check.run((), world);
+                }
+            });
+            warnings
+        };
+
+        // Over budget for several frames, only the first warns
+        assert_eq!(frames(&mut world, 3), 1);
+        world.despawn(ripples[0]);
+        assert_eq!(frames(&mut world, 1), 0);
+        world.spawn(Ripple);
+        assert_eq!(frames(&mut world, 1), 1);
+    }
+
+    #[test]
+    fn peeking_pushes_the_camera_ahead_and_eases_back() {
+        let (mut world, camera) = camera_world();
+        world.spawn((
+            PlayerTag,
+            CameraTarget,
+            FaceDirection(FacingDirection::Right),
+            Transform::default(),
+        ));
+        world
+            .get_mut::<OrthographicProjection>(camera)
+            .unwrap()
+            .area = Rect::from_center_size(Vec2::ZERO, Vec2::new(800.0, 600.0));
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(update_camera);
+            world.get::<CameraValues>(camera).unwrap().peek_offset
+        };
+
+        // Q peeks the way the player faces, up to peek_reach of the view
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyQ);
+        for _ in 0..20 {
+            run_for(&mut world, 0.1);
+        }
+        let peek = run_for(&mut world, 0.1);
+        assert!((peek - Vec2::new(240.0, 0.0)).length() < 1.0, "{peek}");
+        assert!(world.get::<Transform>(camera).unwrap().translation.x > 100.0);
+
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .release(KeyCode::KeyQ);
+        let easing = run_for(&mut world, 0.1);
+        assert!(easing.x > 0.0 && easing.x < peek.x, "{easing}");
+        for _ in 0..20 {
+            run_for(&mut world, 0.1);
+        }
+        assert!(run_for(&mut world, 0.1).length() < 1.0);
+    }
+
+    #[test]
+    fn animation_layout_loads_from_ron() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<AnimationLayout>()
+            .init_asset_loader::<AnimationLayoutLoader>();
+        let handle: Handle<AnimationLayout> = app
+            .world()
+            .resource::<AssetServer>()
+            .load("animations/player.anim.ron");
+        for _ in 0..500 {
+            app.update();
+            if app
+                .world()
+                .resource::<Assets<AnimationLayout>>()
+                .contains(&handle)
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let layouts = app.world().resource::<Assets<AnimationLayout>>();
+        let layout = layouts.get(&handle).expect("the layout loads");
+
+        let (indices, errors) = count_logs(Level::ERROR, || layout.indices());
+        assert_eq!(errors, 0);
+        let starts = |clip: &AnimIndices| (clip.right, clip.left, clip.down, clip.up, clip.frames);
+        assert_eq!(starts(&indices.walk), (0, 8, 16, 24, 8));
+        assert_eq!(starts(&indices.idle), (32, 40, 48, 56, 4));
+        let events = layout.events();
+        assert_eq!(events.0.len(), 2);
+
+        // Half the rows leaves all four idle facings off the grid
+        let small = AnimationLayout {
+            rows: 4,
+            ..ron::de::from_bytes(include_bytes!("../assets/animations/player.anim.ron")).unwrap()
+        };
+        assert_eq!(count_logs(Level::ERROR, || small.indices()).1, 4);
+    }
+
+    #[test]
+    fn impacts_shake_once_in_proportion_to_force() {
+        let mut world = World::new();
+        world.init_resource::<Events<ContactForceEvent>>();
+        world.init_resource::<Events<ShakeEvent>>();
+        world.insert_resource(ImpactShakeSettings {
+            min_force: 100.0,
+            pixels_per_force: 0.01,
+        });
+        let mut shake = IntoSystem::into_system(shake_on_impacts);
+        shake.initialize(&mut world);
+        let (crate_entity, wall) = (Entity::from_raw(1), Entity::from_raw(2));
+        // Shakes sent in a frame where the crate pushes on the wall with `force`
+        let mut frame = |world: &mut World, force: Option<f32>| {
+            if let Some(force) = force {
+                world.send_event(ContactForceEvent {
+                    collider1: crate_entity,
+                    collider2: wall,
+                    total_force: Vec2::new(force, 0.0),
+                    total_force_magnitude: force,
+                    max_force_direction: Vec2::X,
+                    max_force_magnitude: force,
+                });
+            }
+            shake.run((), world);
+            let mut shakes = world.resource_mut::<Events<ShakeEvent>>();
+            shakes
+                .drain()
+                .map(|shake| shake.intensity)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(frame(&mut world, Some(1000.0)), [10.0]);
+        // Still pressed against it
+        assert!(frame(&mut world, Some(400.0)).is_empty());
+        assert!(frame(&mut world, None).is_empty());
+        assert_eq!(frame(&mut world, Some(500.0)), [5.0]);
+    }
+
+    #[test]
+    fn faction_tints_apply_on_change_only() {
+        let player_blue = Color::srgb(0.75, 0.85, 1.0);
+        let mut app = App::new();
+        app.insert_resource(FactionTints(HashMap::from([(
+            Faction::Player,
+            player_blue,
+        )])))
+        .add_systems(Update, apply_faction_tints);
+        let sprite = app
+            .world_mut()
+            .spawn((Faction::Player, Sprite::default()))
+            .id();
+        let color = |app: &App| app.world().get::<Sprite>(sprite).unwrap().color;
+
+        app.update();
+        assert_eq!(color(&app), player_blue);
+
+        // An effect tint on top is left alone until the faction changes
+        let flash = Color::srgb(1.0, 0.3, 0.3);
+        app.world_mut().get_mut::<Sprite>(sprite).unwrap().color = flash;
+        app.update();
+        assert_eq!(color(&app), flash);
+
+        // Enemies have no entry here, so they're untinted
+        *app.world_mut().get_mut::<Faction>(sprite).unwrap() = Faction::Enemy;
+        app.update();
+        assert_eq!(color(&app), Color::WHITE);
+        *app.world_mut().get_mut::<Faction>(sprite).unwrap() = Faction::Player;
+        app.update();
+        assert_eq!(color(&app), player_blue);
+    }
+
+    #[test]
+    fn fog_reveals_the_vision_radius_and_remembers_it() {
+        let mut world = setup_world();
+        world.run_system_once(reveal_fog);
+        let alphas = |world: &mut World| {
+            let image = world.query::<(&FogOfWar, &Handle<Image>)>().single(world).1;
+            let data = &world.resource::<Assets<Image>>().get(image).unwrap().data;
+            data.chunks(4).map(|texel| texel[3]).collect::<Vec<u8>>()
+        };
+        let settings = FogSettings {
+            extent: Vec2::new(1600.0, 1200.0),
+            cell_size: WORLD_SCALE.texels(4.0),
+            vision_radius: 220.0,
+            explored_alpha: 0.6,
+        };
+        let cells = settings.cells();
+        let in_sight = |from: Vec2| {
+            let settings = &settings;
+            (0..cells.y)
+                .flat_map(|y| (0..cells.x).map(move |x| UVec2::new(x, y)))
+                .map(move |cell| settings.cell_center(cell).distance(from) <= 220.0)
+                .collect::<Vec<bool>>()
+        };
+
+        // The player spawns at the origin
+        let seen = in_sight(Vec2::ZERO);
+        assert!(seen.iter().any(|&seen| seen));
+        let expected: Vec<u8> = seen
+            .iter()
+            .map(|&seen| if seen { 0 } else { 255 })
+            .collect();
+        assert_eq!(alphas(&mut world), expected);
+
+        let moved = Vec2::new(500.0, 0.0);
+        let mut player = world.query_filtered::<&mut Transform, With<PlayerTag>>();
+        player.single_mut(&mut world).translation = moved.extend(0.0);
+        world.run_system_once(reveal_fog);
+        let expected: Vec<u8> = seen
+            .iter()
+            .zip(in_sight(moved))
+            .map(|(&seen, visible)| match (visible, seen) {
+                (true, _) => 0,
+                (false, true) => (0.6_f32 * 255.0) as u8,
+                (false, false) => 255,
+            })
+            .collect();
+        assert_eq!(alphas(&mut world), expected);
+    }
+
+    // World with one player moving at `velocity` for get_player_input,
+    // holding `keys`
+    fn input_world(keys: &[KeyCode], settings: MoveSettings, velocity: Vec2) -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Gamepads>();
+        world.init_resource::<Axis<GamepadAxis>>();
+        world.init_resource::<InputContexts>();
+        world.insert_resource(InputSettings {
+            stick_dead_zone: 0.2,
+        });
+        world.insert_resource(MoveQuantization {
+            enabled: false,
+            step: 1.0,
+        });
+        let mut keyboard = ButtonInput::<KeyCode>::default();
+        keys.iter().for_each(|&key| keyboard.press(key));
+        world.insert_resource(keyboard);
+        let player = world
+            .spawn((
+                PlayerTag,
+                Velocity(velocity),
+                settings,
+                FaceDirection(FacingDirection::Down),
+                TerrainEffects {
+                    speed_mult: 1.0,
+                    in_water: false,
+                    in_grass: false,
+                },
+            ))
+            .id();
+        (world, player)
+    }
+
+    // Top speed get_player_input reaches holding `keys`
+    fn held_speed(keys: &[KeyCode], normalize_diagonal: bool) -> f32 {
+        let settings = MoveSettings {
+            normalize_diagonal,
+            ..player_move_settings()
+        };
+        let (mut world, player) = input_world(keys, settings, Vec2::ZERO);
+        let mut input = IntoSystem::into_system(get_player_input);
+        input.initialize(&mut world);
+        for _ in 0..120 {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(1.0 / 60.0));
+            input.run((), &mut world);
+        }
+        world.get::<Velocity>(player).unwrap().0.length()
+    }
+
+    #[test]
+    fn diagonals_are_only_faster_without_normalizing() {
+        let diagonal = [KeyCode::KeyW, KeyCode::KeyD];
+        for normalize_diagonal in [false, true] {
+            let cardinal = held_speed(&[KeyCode::KeyD], normalize_diagonal);
+            assert!((cardinal - 320.0).abs() < 0.01);
+            let diagonal = held_speed(&diagonal, normalize_diagonal);
+            if normalize_diagonal {
+                assert!((diagonal - cardinal).abs() < 0.01);
+            } else {
+                assert!((diagonal - cardinal * 2.0_f32.sqrt()).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn camera_follows_whoever_holds_the_target() {
+        let (mut world, camera) = camera_world();
+        let player = world
+            .spawn((
+                PlayerTag,
+                CameraTarget,
+                FaceDirection(FacingDirection::Down),
+                Transform::default(),
+            ))
+            .id();
+        let other = world.spawn(Transform::from_xyz(300.0, 100.0, 0.0)).id();
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(update_camera);
+            world
+                .get::<Transform>(camera)
+                .unwrap()
+                .translation
+                .truncate()
+        };
+        assert_eq!(run_for(&mut world, 0.1), Vec2::ZERO);
+
+        world.entity_mut(player).remove::<CameraTarget>();
+        world.entity_mut(other).insert(CameraTarget);
+        // Eases over rather than cutting
+        let first = run_for(&mut world, 0.1);
+        assert!(first.x > 0.0 && first.x < 300.0, "{first}");
+        for _ in 0..50 {
+            run_for(&mut world, 0.1);
+        }
+        let settled = run_for(&mut world, 0.1);
+        assert!(
+            (settled - Vec2::new(300.0, 100.0)).length() < 1.0,
+            "{settled}"
+        );
+
+        // With two targets the lowest entity wins
+        world.entity_mut(player).insert(CameraTarget);
+        for _ in 0..50 {
+            run_for(&mut world, 0.1);
+        }
+        assert!(run_for(&mut world, 0.1).length() < 1.0);
+    }
+
+    // Clip starting at the same frame for every facing, 0.1 seconds a frame
+    fn clip(start: usize, frames: usize) -> AnimIndices {
+        AnimIndices {
+            left: start,
+            right: start,
+            up: start,
+            down: start,
+            frames,
+            frame_time: 0.1,
+            max_state_time: None,
+        }
+    }
+
+    // World with one idle sprite for animate_sprites to play, plus `extra`
+    fn animated_world(indices: AnimationInd, extra: impl Bundle) -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.init_resource::<Events<AnimFrameEvent>>();
+        let sprite = world
+            .spawn((
+                indices,
+                AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
+                TextureAtlas::default(),
+                player_move_settings(),
+                FaceDirection(FacingDirection::Down),
+                extra,
+            ))
+            .id();
+        (world, sprite)
+    }
+
+    // Steps animate_sprites one frame time on, returning the atlas index
+    fn step_animation(world: &mut World, sprite: Entity) -> usize {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.1));
+        world.run_system_once(animate_sprites);
+        world.get::<TextureAtlas>(sprite).unwrap().index
+    }
+
+    #[test]
+    fn bored_clip_plays_once_after_idling() {
+        let indices = AnimationInd {
+            walk: clip(20, 4),
+            idle: clip(0, 4),
+            bored: Some(clip(10, 2)),
+            start_walk: None,
+            stop_walk: None,
+        };
+        let boredom = Boredom {
+            after_secs: 0.95,
+            idle_secs: 0.0,
+            playing: false,
+        };
+        let (mut world, sprite) = animated_world(indices, boredom);
+        let frames: Vec<usize> = (0..30)
+            .map(|_| step_animation(&mut world, sprite))
+            .collect();
+
+        // Idle loops, the bored clip plays through once on the tenth frame,
+        // then idle again until another 0.95 seconds have passed
+        let idle = [1, 2, 3, 0];
+        let expected: Vec<usize> = idle
+            .iter()
+            .cycle()
+            .take(9)
+            .copied()
+            .chain([10, 11, 0])
+            .chain(idle.iter().cycle().take(9).copied())
+            .chain([10, 11, 0])
+            .chain(idle.iter().cycle().take(6).copied())
+            .collect();
+        assert_eq!(frames, expected);
+
+        // Walking cuts it short
+        step_animation(&mut world, sprite);
+        let mut boredom = world.get_mut::<Boredom>(sprite).unwrap();
+        boredom.idle_secs = 0.9;
+        world.get_mut::<MoveSettings>(sprite).unwrap().is_walking = true;
+        assert_eq!(step_animation(&mut world, sprite), 20);
+        assert_eq!(world.get::<Boredom>(sprite).unwrap().idle_secs, 0.0);
+    }
+
+    #[test]
+    fn grass_and_water_slow_together_and_grass_rustles() {
+        let mut app = physics_app();
+        app.add_event::<SfxEvent>()
+            .add_event::<SpatialSfxEvent>()
+            .add_systems(
+                Update,
+                (
+                    spawn_water_zones,
+                    spawn_tall_grass,
+                    update_terrain_effects,
+                    spawn_rustles,
+                ),
+            );
+        let size = Vec2::new(100.0, 100.0);
+        app.world_mut().spawn((
+            WaterZone {
+                size,
+                speed_mult: 0.5,
+            },
+            Transform::default(),
+        ));
+        app.world_mut().spawn((
+            TallGrass {
+                size,
+                speed_mult: 0.7,
+            },
+            Transform::from_xyz(50.0, 0.0, 0.0),
+        ));
+        let player = app
+            .world_mut()
+            .spawn((
+                MoveSettings {
+                    is_walking: true,
+                    ..player_move_settings()
+                },
+                TerrainEffects {
+                    speed_mult: 1.0,
+                    in_water: false,
+                    in_grass: false,
+                },
+                RigidBody::KinematicPositionBased,
+                Collider::ball(4.0),
+                TransformBundle::default(),
+            ))
+            .id();
+        let mut speed_mult_at = |x: f32| {
+            app.world_mut()
+                .get_mut::<Transform>(player)
+                .unwrap()
+                .translation
+                .x = x;
+            for _ in 0..30 {
+                app.update();
+            }
+            let speed_mult = app
+                .world()
+                .get::<TerrainEffects>(player)
+                .unwrap()
+                .speed_mult;
+            // Leaves from earlier positions are cleared so they don't count here
+            let mut rustles = app.world_mut().query_filtered::<Entity, With<Rustle>>();
+            let rustles: Vec<Entity> = rustles.iter(app.world()).collect();
+            for &leaf in &rustles {
+                app.world_mut().despawn(leaf);
+            }
+            (speed_mult, !rustles.is_empty())
+        };
+
+        assert_eq!(speed_mult_at(-30.0), (0.5, false));
+        assert_eq!(speed_mult_at(25.0), (0.35, true));
+        assert_eq!(speed_mult_at(80.0), (0.7, true));
+        assert_eq!(speed_mult_at(200.0).0, 1.0);
+
+        // Terrain scales how fast the player can go
+        let settings = player_move_settings();
+        let top_speed = (0..120).fold(Vec2::ZERO, |velocity, _| {
+            step_velocity(velocity, Vec2::X, &settings, 0.35, 1.0 / 60.0)
+        });
+        assert!((top_speed.x - 320.0 * 0.35).abs() < 0.01);
+    }
+
+    #[test]
+    fn camera_eases_towards_its_target_rotation() {
+        let (mut world, camera) = camera_world();
+        world.spawn((
+            PlayerTag,
+            CameraTarget,
+            FaceDirection(FacingDirection::Down),
+            Transform::default(),
+        ));
+        world
+            .get_mut::<CameraValues>(camera)
+            .unwrap()
+            .target_rotation = 0.5;
+        let mut angles = (0..30).map(|_| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(0.1));
+            world.run_system_once(update_camera);
+            let rotation = world.get::<Transform>(camera).unwrap().rotation;
+            rotation.to_euler(EulerRot::XYZ).2
+        });
+        let first = angles.next().unwrap();
+        assert!(first > 0.0 && first < 0.5, "{first}");
+        let mut last = first;
+        for angle in angles {
+            assert!(angle > last || (angle - 0.5).abs() < 1e-4);
+            last = angle;
+        }
+        assert!((last - 0.5).abs() < 1e-3, "{last}");
+    }
+
+    // App with an 800x600 window, the cursor at `cursor`, and a main camera
+    // at `camera_at` whose viewport is worked out before `systems` run
+    fn cursor_app<M>(
+        cursor: Vec2,
+        camera_at: Vec2,
+        systems: impl IntoSystemConfigs<M>,
+    ) -> (App, Entity) {
+        use bevy::render::camera::{camera_system, ManualTextureViews};
+        use bevy::window::{
+            WindowCreated, WindowResized, WindowResolution, WindowScaleFactorChanged,
+        };
+
+        let mut app = physics_app();
+        app.init_asset::<Image>()
+            .init_resource::<ManualTextureViews>()
+            .add_event::<WindowCreated>()
+            .add_event::<WindowResized>()
+            .add_event::<WindowScaleFactorChanged>()
+            .add_systems(
+                Update,
+                systems.after(camera_system::<OrthographicProjection>),
+            )
+            .add_systems(Update, camera_system::<OrthographicProjection>);
+        let mut window = Window {
+            resolution: WindowResolution::new(800.0, 600.0),
+            ..default()
+        };
+        window.set_cursor_position(Some(cursor));
+        let window = app.world_mut().spawn((window, PrimaryWindow)).id();
+        app.world_mut().spawn((
+            MainCameraTag,
+            Camera::default(),
+            OrthographicProjection::default(),
+            GlobalTransform::from_translation(camera_at.extend(0.0)),
+        ));
+        (app, window)
+    }
+
+    #[test]
+    fn tooltips_show_the_topmost_hovered_entity() {
+        let (mut app, window) = cursor_app(Vec2::new(400.0, 300.0), Vec2::ZERO, show_tooltips);
+        app.init_resource::<UiScale>();
+        let text = app
+            .world_mut()
+            .spawn((
+                TooltipText,
+                Text::from_section("", TextStyle::default()),
+                Style::default(),
+                Visibility::Hidden,
+            ))
+            .id();
+        for (z, name) in [(0.0, "below"), (1.0, "above")] {
+            app.world_mut().spawn((
+                Tooltip { text: name.into() },
+                Collider::cuboid(20.0, 20.0),
+                TransformBundle::from_transform(Transform::from_xyz(5.0, 0.0, z)),
+            ));
+        }
+
+        // The first update only gets the colliders into Rapier
+        app.update();
+        app.update();
+        let world = app.world();
+        assert_eq!(world.get::<Visibility>(text), Some(&Visibility::Inherited));
+        assert_eq!(world.get::<Text>(text).unwrap().sections[0].value, "above");
+        let style = world.get::<Style>(text).unwrap();
+        assert_eq!(style.left, Val::Px(400.0 + TOOLTIP_OFFSET.x));
+        assert_eq!(style.top, Val::Px(300.0 + TOOLTIP_OFFSET.y));
+
+        app.world_mut()
+            .get_mut::<Window>(window)
+            .unwrap()
+            .set_cursor_position(Some(Vec2::new(100.0, 100.0)));
+        app.update();
+        assert_eq!(
+            app.world().get::<Visibility>(text),
+            Some(&Visibility::Hidden)
+        );
+    }
+
+    #[test]
+    fn camera_bobs_with_the_walk_cycle_and_eases_out() {
+        let (mut world, camera) = camera_world();
+        let mut settings = player_move_settings();
+        settings.is_walking = true;
+        let player = world
+            .spawn((
+                PlayerTag,
+                AnimationInd {
+                    walk: clip(20, 4),
+                    idle: clip(0, 4),
+                    bored: None,
+                    start_walk: None,
+                    stop_walk: None,
+                },
+                AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
+                TextureAtlas {
+                    index: 20,
+                    ..default()
+                },
+                FaceDirection(FacingDirection::Down),
+                Velocity(Vec2::new(settings.speed, 0.0)),
+                settings,
+            ))
+            .id();
+        let mut bob = IntoSystem::into_system(bob_camera);
+        bob.initialize(&mut world);
+        let mut step = |world: &mut World, index: usize| {
+            world.get_mut::<TextureAtlas>(player).unwrap().index = index;
+            world.get_mut::<Transform>(camera).unwrap().translation = Vec3::ZERO;
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(0.1));
+            bob.run((), world);
+            world.get::<CameraValues>(camera).unwrap().bob_offset
+        };
+
+        // Eases in, dipping down on the first frame and up a quarter cycle on
+        let first = step(&mut world, 20);
+        assert!(first.y < 0.0 && first.x.abs() < 1e-4, "{first}");
+        for _ in 0..30 {
+            step(&mut world, 20);
+        }
+        let dipped = step(&mut world, 20);
+        assert!(dipped.y < first.y);
+        assert!((dipped.y + 2.0).abs() < 1e-2, "{dipped}");
+        let raised = step(&mut world, 21);
+        assert!((raised.y - 2.0).abs() < 1e-2, "{raised}");
+        assert_eq!(
+            world.get::<Transform>(camera).unwrap().translation,
+            raised.extend(0.0)
+        );
+
+        // Stopping on an idle frame keeps the phase and eases the strength out
+        world.get_mut::<MoveSettings>(player).unwrap().is_walking = false;
+        let easing = step(&mut world, 0);
+        assert!(easing.y > 0.0 && easing.y < raised.y, "{easing}");
+        for _ in 0..60 {
+            step(&mut world, 0);
+        }
+        assert!(step(&mut world, 0).length() < 1e-3);
+    }
+
+    #[test]
+    fn letting_go_stops_the_player_dead() {
+        let mut settings = player_move_settings();
+        settings.is_walking = true;
+        let (mut world, player) = input_world(&[], settings, Vec2::new(320.0, -40.0));
+        let mut input = IntoSystem::into_system(get_player_input);
+        input.initialize(&mut world);
+        let mut step = |world: &mut World| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(1.0 / 60.0));
+            input.run((), world);
+            world.get::<Velocity>(player).unwrap().0
+        };
+
+        let first = step(&mut world);
+        assert!(first.length() > 0.0 && first.length() < 320.0, "{first}");
+        assert!(!world.get::<MoveSettings>(player).unwrap().is_walking);
+        // Friction snaps to rest below stop_speed instead of creeping forever
+        for _ in 0..120 {
+            step(&mut world);
+        }
+        assert_eq!(step(&mut world), Vec2::ZERO);
+        assert!(!world.get::<MoveSettings>(player).unwrap().is_walking);
+    }
+
+    #[test]
+    fn auto_save_writes_its_slot_on_the_interval() {
+        let dir = temp_dir("autosave");
+        let dir_path = dir.to_str().unwrap();
+        let mut world = level_world();
+        world.init_resource::<WorldFlags>();
+        world.init_resource::<Time>();
+        world.insert_resource(Playtime(30.0));
+        world.insert_resource(AutoSave {
+            interval: Some(Timer::from_seconds(1.0, TimerMode::Repeating)),
+            dir: dir_path.to_string(),
+        });
+        fs::create_dir_all(&dir).unwrap();
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(auto_save);
+            list_slots(dir_path)
+        };
+
+        assert!(run_for(&mut world, 0.6).is_empty());
+        let slots = run_for(&mut world, 0.6);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(slots[0].slot, AUTOSAVE_SLOT);
+        assert_eq!(slots[0].playtime_secs, 30.0);
+
+        world.resource_mut::<AutoSave>().interval = None;
+        fs::remove_dir_all(&dir).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        assert!(run_for(&mut world, 5.0).is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn frame_events_fire_once_on_entering_their_frame() {
+        let indices = AnimationInd {
+            walk: clip(20, 4),
+            idle: clip(0, 4),
+            bored: None,
+            start_walk: None,
+            stop_walk: None,
+        };
+        let tag = |clip, frame, tag: &str| FrameTag {
+            clip,
+            frame,
+            tag: tag.to_string(),
+        };
+        let events = AnimationEvents(vec![
+            tag(AnimClip::Idle, 2, "blink"),
+            tag(AnimClip::Walk, 0, "footstep"),
+        ]);
+        let (mut world, sprite) = animated_world(indices, events);
+        let drain = |world: &mut World| -> Vec<String> {
+            let mut events = world.resource_mut::<Events<AnimFrameEvent>>();
+            events
+                .drain()
+                .map(|event| {
+                    assert_eq!(event.entity, sprite);
+                    event.tag
+                })
+                .collect()
+        };
+
+        // Idle goes 1, 2, 3, 0, 1, 2
+        let tags: Vec<String> = (0..6)
+            .map(|_| {
+                step_animation(&mut world, sprite);
+                drain(&mut world).join(",")
+            })
+            .collect();
+        assert_eq!(tags, ["", "blink", "", "", "", "blink"]);
+
+        // Staying on the frame doesn't send it again
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.05));
+        world.run_system_once(animate_sprites);
+        assert!(drain(&mut world).is_empty());
+
+        world.get_mut::<MoveSettings>(sprite).unwrap().is_walking = true;
+        assert_eq!(step_animation(&mut world, sprite), 20);
+        assert_eq!(drain(&mut world), ["footstep"]);
+    }
+
+    #[test]
+    fn dialogue_types_out_and_skips_ahead_on_interact() {
+        let mut world = World::new();
+        world.init_resource::<Time<Real>>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<ButtonInput<GamepadButton>>();
+        world.init_resource::<Gamepads>();
+        world.init_resource::<Events<SfxEvent>>();
+        let mut contexts = InputContexts::default();
+        contexts.push(InputContext::Dialogue);
+        world.insert_resource(contexts);
+        let dialogue = world
+            .spawn((
+                Dialogue {
+                    lines: vec!["Hello there".to_string(), "Bye".to_string()],
+                    chars_per_sec: 10.0,
+                    line: 0,
+                    revealed: 0.0,
+                },
+                Text::from_section("", TextStyle::default()),
+            ))
+            .id();
+        let run_for = |world: &mut World, secs: f32, interact: bool| {
+            let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+            keyboard.clear();
+            if interact {
+                keyboard.release(KeyCode::KeyE);
+                keyboard.press(KeyCode::KeyE);
+            }
+            world
+                .resource_mut::<Time<Real>>()
+                .update_with_duration(Duration::from_secs_f32(secs));
+            world.run_system_once(advance_dialogue);
+            let text = world.get::<Text>(dialogue)?;
+            Some(text.sections[0].value.clone())
+        };
+
+        assert_eq!(run_for(&mut world, 0.0, false).unwrap(), "");
+        assert_eq!(run_for(&mut world, 0.3, false).unwrap(), "Hel");
+        assert_eq!(run_for(&mut world, 0.25, false).unwrap(), "Hello");
+        // The first press finishes the line, the next moves on
+        assert_eq!(run_for(&mut world, 0.0, true).unwrap(), "Hello there");
+        assert_eq!(run_for(&mut world, 0.0, true).unwrap(), "");
+        assert_eq!(run_for(&mut world, 0.1, false).unwrap(), "B");
+        assert_eq!(run_for(&mut world, 0.0, true).unwrap(), "Bye");
+        assert!(world
+            .resource::<InputContexts>()
+            .is_active(InputContext::Dialogue));
+
+        assert_eq!(run_for(&mut world, 0.0, true), None);
+        assert!(world.get_entity(dialogue).is_none());
+        assert!(world
+            .resource::<InputContexts>()
+            .is_active(InputContext::Gameplay));
+    }
+
+    #[test]
+    fn weather_particles_fall_off_screen_and_get_reused() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.insert_resource(Weather {
+            kind: WeatherKind::Rain,
+            intensity: 1.0,
+            wind: 0.0,
+        });
+        let mut spawn = IntoSystem::into_system(spawn_weather);
+        spawn.initialize(&mut world);
+        let mut run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            spawn.run((), world);
+            spawn.apply_deferred(world);
+            world.run_system_once(move_weather);
+            let mut particles = world.query::<(&WeatherParticle, &Visibility)>();
+            let shown = particles
+                .iter(world)
+                .filter(|(_, visibility)| **visibility != Visibility::Hidden)
+                .count();
+            (particles.iter(world).len(), shown)
+        };
+
+        // 300 a second, the half left over carries into the next frame
+        assert_eq!(run_for(&mut world, 0.125), (37, 37));
+        assert_eq!(run_for(&mut world, 0.125), (75, 75));
+        // Rain crosses the screen in two thirds of a second, so the first
+        // frame's drops are gone and the next frame's are about to go
+        assert_eq!(run_for(&mut world, 0.5), (225, 188));
+        // The 37 hidden ones are reused before spawning more
+        assert_eq!(run_for(&mut world, 0.5), (338, 150));
+        assert_eq!(run_for(&mut world, 10.0), (MAX_WEATHER_PARTICLES, 0));
+
+        world.resource_mut::<Weather>().kind = WeatherKind::Clear;
+        assert_eq!(run_for(&mut world, 0.5), (MAX_WEATHER_PARTICLES, 0));
+    }
+
+    #[test]
+    fn debug_keys_toggle_their_overlays() {
+        #[derive(Resource, Default)]
+        struct Draws(usize);
+
+        let mut app = App::new();
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<Draws>()
+            .insert_resource(DebugFlags {
+                keys_enabled: true,
+                controllers: false,
+                velocities: false,
+                interact_ranges: false,
+            })
+            .add_systems(
+                Update,
+                (
+                    toggle_debug_flags,
+                    (|mut draws: ResMut<Draws>| draws.0 += 1)
+                        .run_if(debug_flag(|flags| flags.velocities)),
+                )
+                    .chain(),
+            );
+        let frame = |app: &mut App, key: Option<KeyCode>| {
+            let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+            keyboard.reset_all();
+            if let Some(key) = key {
+                keyboard.press(key);
+            }
+            app.update();
+            app.world().resource::<Draws>().0
+        };
+
+        assert_eq!(frame(&mut app, None), 0);
+        // Other overlays' keys leave it alone
+        assert_eq!(frame(&mut app, Some(KeyCode::F6)), 0);
+        assert!(app.world().resource::<DebugFlags>().controllers);
+        assert_eq!(frame(&mut app, Some(KeyCode::F7)), 1);
+        assert_eq!(frame(&mut app, None), 2);
+        assert_eq!(frame(&mut app, Some(KeyCode::F7)), 2);
+        assert_eq!(frame(&mut app, None), 2);
+    }
+
+    #[test]
+    fn camera_easings_close_the_gap_differently() {
+        let trajectory = |easing: CameraEasing| -> Vec<f32> {
+            let (mut world, camera) = camera_world();
+            world.get_mut::<CameraValues>(camera).unwrap().easing = easing;
+            world.spawn((
+                PlayerTag,
+                CameraTarget,
+                FaceDirection(FacingDirection::Down),
+                Transform::from_xyz(100.0, 0.0, 0.0),
+            ));
+            (0..60)
+                .map(|_| {
+                    world
+                        .resource_mut::<Time>()
+                        .advance_by(Duration::from_secs_f32(0.05));
+                    world.run_system_once(update_camera);
+                    world.get::<Transform>(camera).unwrap().translation.x
+                })
+                .collect()
+        };
+        let steps = |xs: &[f32]| -> Vec<f32> {
+            std::iter::once(xs[0])
+                .chain(xs.windows(2).map(|pair| pair[1] - pair[0]))
+                .collect()
+        };
+
+        // Constant speed until it arrives, then stops dead
+        let linear = trajectory(CameraEasing::Linear { speed: 200.0 });
+        assert!(steps(&linear[..10])
+            .iter()
+            .all(|step| (step - 10.0).abs() < 1e-3));
+        assert!(linear[10..].iter().all(|&x| x == 100.0));
+
+        // Biggest step first
+        let exponential = steps(&trajectory(CameraEasing::Exponential));
+        assert!(exponential.windows(2).all(|pair| pair[1] < pair[0]));
+
+        // Speeds up before slowing down, and never overshoots
+        let ease_in_out = trajectory(CameraEasing::EaseInOut);
+        let ease_steps = steps(&ease_in_out);
+        assert!(ease_steps[1] > ease_steps[0]);
+        assert!(ease_in_out.iter().all(|&x| x <= 100.0));
+        assert!(ease_in_out[59] > 95.0);
+
+        // Bounces past the target when underdamped, then settles
+        let spring = trajectory(CameraEasing::Spring {
+            stiffness: 100.0,
+            damping: 4.0,
+        });
+        assert!(spring.iter().any(|&x| x > 110.0));
+        assert!((spring[59] - 100.0).abs() < 5.0, "{}", spring[59]);
+    }
+
+    #[test]
+    fn number_keys_spawn_crates_under_the_cursor() {
+        let (mut app, _) = cursor_app(
+            Vec2::new(500.0, 200.0),
+            Vec2::new(50.0, 20.0),
+            debug_spawn_at_cursor,
+        );
+        app.init_resource::<ButtonInput<KeyCode>>();
+        let crates = |app: &mut App, key: Option<KeyCode>| {
+            let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+            keyboard.reset_all();
+            if let Some(key) = key {
+                keyboard.press(key);
+            }
+            app.update();
+            let mut crates = app
+                .world_mut()
+                .query_filtered::<(&LevelCrate, &Transform), With<LevelEntity>>();
+            crates
+                .iter(app.world())
+                .map(|(level_crate, transform)| (level_crate.preset, transform.translation))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(crates(&mut app, None).is_empty());
+        assert!(crates(&mut app, Some(KeyCode::Digit4)).is_empty());
+        let spawned = crates(&mut app, Some(KeyCode::Digit2));
+        assert_eq!(spawned.len(), 1);
+        assert!(matches!(spawned[0].0, CratePreset::Slippery));
+        // 100 pixels right of and above the screen centre, y points up in the world
+        assert!(spawned[0].1.abs_diff_eq(Vec3::new(150.0, 120.0, 0.0), 1e-3));
+        assert_eq!(crates(&mut app, None).len(), 1);
+    }
+
+    #[test]
+    fn player_spawns_with_the_configured_collider() {
+        let shape = |var| {
+            let mut world = setup_world_with(PlayerCollider::parse(var));
+            let collider = world
+                .query_filtered::<&Collider, With<PlayerTag>>()
+                .single(&world);
+            match collider.as_typed_shape() {
+                ColliderView::Ball(ball) => format!("ball {}", ball.radius()),
+                // Upright, so the segment runs along y
+                ColliderView::Capsule(capsule) => {
+                    let segment = capsule.segment();
+                    let (a, b) = (segment.a(), segment.b());
+                    assert_eq!(a.x, b.x);
+                    format!("capsule {} {}", (b.y - a.y) / 2.0, capsule.radius())
+                }
+                ColliderView::Cuboid(cuboid) => format!("cuboid {}", cuboid.half_extents()),
+                _ => "other".to_string(),
+            }
+        };
+
+        assert_eq!(shape(None), "ball 7");
+        assert_eq!(shape(Some("wobbly")), "ball 7");
+        assert_eq!(shape(Some("capsule")), "capsule 3 5");
+        assert_eq!(shape(Some(" cuboid")), "cuboid [6, 7]");
+    }
+
+    #[test]
+    fn frozen_entities_hold_still_and_resume_where_they_were() {
+        let indices = AnimationInd {
+            walk: clip(20, 4),
+            idle: clip(0, 4),
+            bored: None,
+            start_walk: None,
+            stop_walk: None,
+        };
+        let (mut world, entity) = animated_world(
+            indices,
+            (
+                KinematicCharacterController::default(),
+                Transform::default(),
+                Velocity(Vec2::new(40.0, 0.0)),
+                Noclip,
+            ),
+        );
+        world.insert_resource(MoveQuantization {
+            enabled: false,
+            step: 1.0,
+        });
+        let step = |world: &mut World| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(0.1));
+            world.run_system_once(apply_kinematics);
+            world.run_system_once(animate_sprites);
+            let x = world.get::<Transform>(entity).unwrap().translation.x;
+            (x.round(), world.get::<TextureAtlas>(entity).unwrap().index)
+        };
+
+        assert_eq!(step(&mut world), (4.0, 1));
+        assert_eq!(step(&mut world), (8.0, 2));
+        world.run_system_once(move |mut commands: Commands| commands.freeze(entity));
+        assert_eq!(step(&mut world), (8.0, 2));
+        assert_eq!(step(&mut world), (8.0, 2));
+        assert_eq!(
+            world.get::<Velocity>(entity).unwrap().0,
+            Vec2::new(40.0, 0.0)
+        );
+        world.run_system_once(move |mut commands: Commands| commands.unfreeze(entity));
+        assert_eq!(step(&mut world), (12.0, 3));
+    }
+
+    #[test]
+    fn level_intro_holds_input_until_the_pan_ends_or_is_skipped() {
+        use bevy::time::TimeUpdateStrategy;
+
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<GamepadButton>>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<InputContexts>()
+            .insert_resource(InputSettings {
+                stick_dead_zone: 0.2,
+            })
+            .insert_resource(MoveQuantization {
+                enabled: false,
+                step: 1.0,
+            })
+            .insert_resource(LevelIntro {
+                waypoints: vec![Vec2::new(100.0, 0.0), Vec2::new(0.0, 100.0)],
+                per_point_time: 0.5,
+            })
+            .add_systems(
+                Update,
+                (start_level_intro, run_level_intro, get_player_input).chain(),
+            );
+        let player = app
+            .world_mut()
+            .spawn((
+                PlayerTag,
+                CameraTarget,
+                Transform::default(),
+                Velocity(Vec2::ZERO),
+                player_move_settings(),
+                FaceDirection(FacingDirection::Down),
+                TerrainEffects {
+                    speed_mult: 1.0,
+                    in_water: false,
+                    in_grass: false,
+                },
+            ))
+            .id();
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyD);
+        // Frames until walking right gets the player moving again
+        let frames_held = |app: &mut App| {
+            (0..40)
+                .take_while(|_| {
+                    app.update();
+                    let world = app.world();
+                    let moving = world.get::<Velocity>(player).unwrap().0 != Vec2::ZERO;
+                    let in_cutscene = world
+                        .resource::<InputContexts>()
+                        .is_active(InputContext::Cutscene);
+                    assert_ne!(moving, in_cutscene);
+                    assert_eq!(world.get::<CameraTarget>(player).is_some(), !in_cutscene);
+                    !moving
+                })
+                .count()
+        };
+
+        // Half a second on each waypoint and on the player
+        assert_eq!(frames_held(&mut app), 15);
+        assert_eq!(frames_held(&mut app), 0);
+
+        // Loading a save restarts it, interacting skips it
+        let world = app.world_mut();
+        world.get_mut::<Velocity>(player).unwrap().0 = Vec2::ZERO;
+        world.resource_mut::<LevelIntro>().set_changed();
+        app.update();
+        assert_eq!(app.world().get::<Velocity>(player).unwrap().0, Vec2::ZERO);
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyE);
+        assert_eq!(frames_held(&mut app), 0);
+    }
+
+    #[test]
+    fn each_axis_follows_at_its_own_rate() {
+        let (mut world, camera) = camera_world();
+        world.get_mut::<CameraValues>(camera).unwrap().lerp_factor = Vec2::new(8.0, 1.0);
+        world.spawn((
+            PlayerTag,
+            CameraTarget,
+            FaceDirection(FacingDirection::Down),
+            Transform::from_xyz(100.0, 100.0, 0.0),
+        ));
+        let mut run_for = |secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(update_camera);
+            world
+                .get::<Transform>(camera)
+                .unwrap()
+                .translation
+                .truncate()
+        };
+
+        // Snappy sideways, lazy up and down
+        let first = run_for(0.1);
+        assert!(first.x > 50.0 && first.y < 15.0, "{first}");
+        let later = run_for(0.4);
+        assert!(later.x > 95.0 && later.y < 50.0, "{later}");
+        let settled = run_for(5.0);
+        assert!(settled.abs_diff_eq(Vec2::splat(100.0), 1.0), "{settled}");
+    }
+
+    #[test]
+    fn one_time_hints_stay_shown_after_loading_a_save() {
+        let dir = temp_dir("flags");
+        let dir_path = dir.to_str().unwrap();
+        let walking_world = || {
+            let mut world = level_world();
+            world.init_resource::<WorldFlags>();
+            world.init_resource::<HintQueue>();
+            world.init_resource::<InputContexts>();
+            world.init_resource::<Playtime>();
+            let mut settings = player_move_settings();
+            settings.is_walking = true;
+            world.spawn((PlayerTag, settings, Transform::default()));
+            world
+        };
+        let saving_hints = |world: &mut World| {
+            world.resource_mut::<HintQueue>().pending.clear();
+            world.run_system_once(contextual_hints);
+            let hints = world.resource::<HintQueue>();
+            hints
+                .pending
+                .iter()
+                .filter(|(text, _)| text.contains("{save}"))
+                .count()
+        };
+
+        let mut world = walking_world();
+        assert_eq!(saving_hints(&mut world), 1);
+        assert_eq!(saving_hints(&mut world), 0);
+        save_to_slot(&mut world, dir_path, 1).unwrap();
+
+        // A new session has no flags until the save is loaded
+        let mut world = walking_world();
+        assert!(load_from_slot(&mut world, dir_path, 1).unwrap());
+        assert!(world.resource::<WorldFlags>().is_set("hint_saving"));
+        assert_eq!(saving_hints(&mut world), 0);
+        assert_eq!(saving_hints(&mut walking_world()), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn walk_transitions_play_between_idle_and_walk() {
+        let indices = AnimationInd {
+            walk: clip(20, 4),
+            idle: clip(0, 4),
+            bored: None,
+            start_walk: Some(clip(30, 2)),
+            stop_walk: Some(clip(40, 2)),
+        };
+        let (mut world, sprite) = animated_world(indices, WalkTransition::default());
+        let frames = |world: &mut World, walking: bool, count: usize| -> Vec<usize> {
+            world.get_mut::<MoveSettings>(sprite).unwrap().is_walking = walking;
+            (0..count).map(|_| step_animation(world, sprite)).collect()
+        };
+
+        assert_eq!(frames(&mut world, false, 2), [1, 2]);
+        assert_eq!(frames(&mut world, true, 7), [30, 31, 20, 21, 22, 23, 20]);
+        assert_eq!(frames(&mut world, false, 4), [40, 41, 0, 1]);
+
+        // Without transition clips it cuts straight across
+        world.entity_mut(sprite).insert(AnimationInd {
+            walk: clip(20, 4),
+            idle: clip(0, 4),
+            bored: None,
+            start_walk: None,
+            stop_walk: None,
+        });
+        assert_eq!(frames(&mut world, true, 2), [20, 21]);
+        assert_eq!(frames(&mut world, false, 2), [0, 1]);
+    }
+
+    #[test]
+    fn lights_brighten_the_lightmap_within_their_radius() {
+        let mut world = setup_world();
+        let lights: Vec<Entity> = world
+            .query_filtered::<Entity, With<Light2d>>()
+            .iter(&world)
+            .collect();
+        for light in lights {
+            world.entity_mut(light).remove::<Light2d>();
+        }
+        world.spawn((MainCameraTag, GlobalTransform::default()));
+        let light = |x: f32, y: f32| {
+            (
+                Light2d {
+                    radius: 80.0,
+                    color: Color::srgb(1.0, 0.5, 0.0),
+                    intensity: 1.0,
+                },
+                GlobalTransform::from_xyz(x, y, 0.0),
+            )
+        };
+        world.spawn(light(100.0, -100.0));
+        world.resource_mut::<LightingSettings>().max_lights = 1;
+        // Further from the camera than the first, so it's left out
+        world.spawn(light(-300.0, 300.0));
+        world.run_system_once(update_lightmap);
+
+        let (extent, cell_size, darkness) = (
+            world.resource::<FogSettings>().extent,
+            world.resource::<LightingSettings>().cell_size,
+            world.resource::<LightingSettings>().darkness,
+        );
+        let mut lightmaps = world.query::<(&Lightmap, &Handle<Image>)>();
+        let (lightmap, image) = lightmaps.single(&world);
+        let image = world.resource::<Assets<Image>>().get(image).unwrap();
+        let texel = |x: f32, y: f32| {
+            let cell = ((Vec2::new(x, y) + extent * Vec2::new(0.5, -0.5)) * Vec2::new(1.0, -1.0)
+                / cell_size)
+                .as_uvec2();
+            let i = ((cell.y * lightmap.cells.x + cell.x) * 4) as usize;
+            <[u8; 4]>::try_from(&image.data[i..i + 4]).unwrap()
+        };
+
+        let dark = (darkness * 255.0) as u8;
+        let [r, g, b, centre] = texel(100.0, -100.0);
+        let [.., halfway] = texel(140.0, -100.0);
+        assert!(centre < halfway && halfway < dark, "{centre} {halfway}");
+        // Tinted towards the light's colour
+        assert!(r > g && g > b, "{r} {g} {b}");
+        assert_eq!(texel(100.0, -190.0), [0, 0, 0, dark]);
+        assert_eq!(texel(-300.0, 300.0), [0, 0, 0, dark]);
+    }
+
+    #[test]
+    fn sudden_stops_squash_the_sprite_then_it_recovers() {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        let sprite = world
+            .spawn((
+                Sprite::default(),
+                SquashStretch {
+                    strength: 0.001,
+                    max_deform: 0.2,
+                    rate: 10.0,
+                    last_velocity: Vec2::new(320.0, 0.0),
+                    deform: Vec2::ONE,
+                },
+                Velocity(Vec2::ZERO),
+            ))
+            .id();
+        let step = |world: &mut World| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(0.05));
+            world.run_system_once(squash_and_stretch);
+            world.get::<Sprite>(sprite).unwrap().custom_size
+        };
+
+        // Braking sideways squashes it narrower and taller
+        let base = TILE_TEXELS as f32;
+        let squashed = step(&mut world).unwrap();
+        assert!(squashed.x < base && squashed.y > base, "{squashed}");
+
+        // Frozen, it holds its shape
+        world.entity_mut(sprite).insert(Frozen);
+        assert_eq!(step(&mut world), Some(squashed));
+        world.entity_mut(sprite).remove::<Frozen>();
+
+        let easing = step(&mut world).unwrap();
+        assert!(easing.x > squashed.x && easing.y < squashed.y, "{easing}");
+        for _ in 0..40 {
+            step(&mut world);
+        }
+        assert_eq!(step(&mut world), None);
+        assert_eq!(
+            world.get::<SquashStretch>(sprite).unwrap().last_velocity,
+            Vec2::ZERO
+        );
+    }
+
+    #[test]
+    fn noclip_passes_through_walls_and_leaves_them_clear() {
+        let mut app = physics_app();
+        app.init_resource::<ButtonInput<KeyCode>>()
+            .insert_resource(MoveQuantization {
+                enabled: false,
+                step: 1.0,
+            })
+            .add_systems(Update, (toggle_noclip, apply_kinematics).chain());
+        app.world_mut()
+            .spawn((Collider::cuboid(10.0, 100.0), TransformBundle::default()));
+        let player = app
+            .world_mut()
+            .spawn((
+                PlayerTag,
+                RigidBody::KinematicPositionBased,
+                Collider::ball(8.0),
+                KinematicCharacterController::default(),
+                Velocity(Vec2::new(240.0, 0.0)),
+                TransformBundle::from_transform(Transform::from_xyz(-60.0, 0.0, 0.0)),
+            ))
+            .id();
+        let run = |app: &mut App, frames: usize, toggle: bool| {
+            let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+            keyboard.reset_all();
+            if toggle {
+                keyboard.press(KeyCode::F10);
+            }
+            for _ in 0..frames {
+                app.update();
+                app.world_mut()
+                    .resource_mut::<ButtonInput<KeyCode>>()
+                    .reset_all();
+            }
+            app.world().get::<Transform>(player).unwrap().translation.x
+        };
+
+        // Stopped at the wall's face
+        let blocked = run(&mut app, 30, false);
+        assert!((-19.0..-17.0).contains(&blocked), "{blocked}");
+        // Straight through it
+        assert!(run(&mut app, 1, true) > blocked);
+        assert!(run(&mut app, 20, false) > 30.0);
+
+        // Leaving noclip inside the wall puts the player beside it
+        app.world_mut().get_mut::<Velocity>(player).unwrap().0 = Vec2::ZERO;
+        app.world_mut()
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation
+            .x = 4.0;
+        let nudged = run(&mut app, 1, true);
+        assert!(nudged.abs() >= 18.0, "{nudged}");
+        assert!(app.world().get::<Noclip>(player).is_none());
+
+        // And the wall blocks again
+        let toward_wall = -nudged.signum() * 240.0;
+        app.world_mut().get_mut::<Velocity>(player).unwrap().0 = Vec2::new(toward_wall, 0.0);
+        let x = run(&mut app, 30, false);
+        assert!(x.abs() > 17.0 && x.signum() == nudged.signum(), "{x}");
+    }
+
+    #[test]
+    fn frame_pair_centres_between_both_and_zooms_to_fit() {
+        let (mut world, camera) = camera_world();
+        let player = world
+            .spawn((
+                PlayerTag,
+                CameraTarget,
+                FaceDirection(FacingDirection::Down),
+                Transform::default(),
+                GlobalTransform::default(),
+            ))
+            .id();
+        let level_crate = |x: f32, y: f32| {
+            let transform = Transform::from_xyz(x, y, 0.0);
+            (
+                LevelCrate {
+                    size: Vec2::splat(20.0),
+                    preset: CratePreset::Heavy,
+                },
+                transform,
+                GlobalTransform::from(transform),
+            )
+        };
+        let boss = world.spawn(level_crate(1200.0, 0.0)).id();
+        world.spawn(level_crate(-900.0, 900.0));
+
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyF);
+        world.run_system_once(debug_frame_pair);
+        let pair = world.get::<FramePair>(camera).unwrap();
+        assert_eq!((pair.a, pair.b), (player, boss));
+
+        // Stands in for Bevy's camera_system, which sizes the area to the scale
+        let view = Vec2::new(800.0, 600.0);
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(update_camera);
+            let mut projection = world.get_mut::<OrthographicProjection>(camera).unwrap();
+            projection.area = Rect::from_center_size(Vec2::ZERO, view * projection.scale);
+            let scale = projection.scale;
+            let position = world
+                .get::<Transform>(camera)
+                .unwrap()
+                .translation
+                .truncate();
+            (position, scale)
+        };
+
+        let (position, scale) = run_for(&mut world, 0.1);
+        assert!(
+            position.x > 0.0 && position.x < 600.0 && scale > 1.0,
+            "{position} {scale}"
+        );
+        for _ in 0..50 {
+            run_for(&mut world, 0.1);
+        }
+        // 1200 pixels apart plus 64 either side, in an 800 pixel wide view
+        let (position, scale) = run_for(&mut world, 0.1);
+        assert!(
+            position.abs_diff_eq(Vec2::new(600.0, 0.0), 0.5),
+            "{position}"
+        );
+        assert!((scale - 1328.0 / 800.0).abs() < 1e-3, "{scale}");
+
+        // Held at max_zoom once they're too far apart
+        *world.get_mut::<GlobalTransform>(boss).unwrap() =
+            GlobalTransform::from_xyz(4000.0, 0.0, 0.0);
+        for _ in 0..50 {
+            run_for(&mut world, 0.1);
+        }
+        let (position, scale) = run_for(&mut world, 0.1);
+        assert!(
+            position.abs_diff_eq(Vec2::new(2000.0, 0.0), 0.5),
+            "{position}"
+        );
+        assert!((scale - 2.0).abs() < 1e-3, "{scale}");
+
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        keyboard.press(KeyCode::KeyF);
+        world.run_system_once(debug_frame_pair);
+        assert!(world.get::<FramePair>(camera).is_none());
+    }
+
+    #[test]
+    fn sprite_colliders_are_traced_once_per_image_and_threshold() {
+        let mut world = setup_world();
+        let rock = world.resource::<GameAssets>().rock.clone();
+        let mut props = world.query_filtered::<&Handle<Image>, With<ColliderFromSprite>>();
+        assert_eq!(props.iter(&world).collect::<Vec<_>>(), [&rock]);
+
+        let mut world = World::new();
+        world.init_resource::<SpriteColliders>();
+        let mut images = Assets::<Image>::default();
+        let block = images.add(alpha_image(&["......", ".####.", ".####.", "......"]));
+        let clear = images.add(alpha_image(&["....", "...."]));
+        world.insert_resource(images);
+        let unloaded = Handle::<Image>::weak_from_u128(7);
+        let spawn = |world: &mut World, image: &Handle<Image>, alpha_threshold| {
+            world
+                .spawn((image.clone(), ColliderFromSprite { alpha_threshold }))
+                .id()
+        };
+        let sprites = [
+            spawn(&mut world, &block, 128),
+            spawn(&mut world, &block, 128),
+            spawn(&mut world, &block, 255),
+            spawn(&mut world, &clear, 128),
+            spawn(&mut world, &unloaded, 128),
+        ];
+        world.run_system_once(generate_sprite_colliders);
+
+        let traced = |world: &World, sprite| {
+            (
+                world.get::<Collider>(sprite).is_some(),
+                world.get::<ColliderFromSprite>(sprite).is_some(),
+            )
+        };
+        let results: Vec<_> = sprites
+            .iter()
+            .map(|&sprite| traced(&world, sprite))
+            .collect();
+        // The unloaded one waits, the clear one gets no collider
+        assert_eq!(
+            results,
+            [
+                (true, false),
+                (true, false),
+                (true, false),
+                (false, false),
+                (false, true)
+            ]
+        );
+        let cache = &world.resource::<SpriteColliders>().0;
+        assert_eq!(cache.len(), 3);
+        assert!(cache[&(clear.id(), 128)].is_none());
+
+        // Later sprites reuse the traced collider rather than tracing the image again
+        *world
+            .resource_mut::<Assets<Image>>()
+            .get_mut(&block)
+            .unwrap() = alpha_image(&["....", "...."]);
+        let late = spawn(&mut world, &block, 128);
+        world.run_system_once(generate_sprite_colliders);
+        assert_eq!(traced(&world, late), (true, false));
+        assert_eq!(world.resource::<SpriteColliders>().0.len(), 3);
+    }
+
+    #[test]
+    fn parallax_layers_scroll_at_their_fraction_and_wrap() {
+        let (mut world, camera) = camera_world();
+        world
+            .get_mut::<OrthographicProjection>(camera)
+            .unwrap()
+            .area = Rect::from_center_size(Vec2::ZERO, Vec2::new(800.0, 600.0));
+        let tile_size = Vec2::splat(64.0);
+        let layers = [Vec2::new(0.5, 0.25), Vec2::ZERO].map(|factor| {
+            world
+                .spawn((
+                    Sprite::default(),
+                    Transform::from_xyz(0.0, 0.0, -5.0),
+                    ParallaxLayer { factor, tile_size },
+                ))
+                .id()
+        });
+        // Where each layer sits relative to the camera
+        let move_to = |world: &mut World, x: f32, y: f32| {
+            world.get_mut::<Transform>(camera).unwrap().translation = Vec3::new(x, y, 0.0);
+            world.run_system_once(scroll_parallax);
+            layers.map(|layer| {
+                let translation = world.get::<Transform>(layer).unwrap().translation;
+                assert_eq!(translation.z, -5.0);
+                translation.truncate() - Vec2::new(x, y)
+            })
+        };
+
+        let [before, sky_before] = move_to(&mut world, 10.0, 10.0);
+        let [after, sky_after] = move_to(&mut world, 30.0, 30.0);
+        assert_eq!(after - before, Vec2::new(-10.0, -5.0));
+        // A factor of 0 stays put on screen, like the sky
+        assert_eq!((sky_before, sky_after), (Vec2::ZERO, Vec2::ZERO));
+
+        // Far away it has wrapped round rather than being left behind
+        let [far, _] = move_to(&mut world, 10_030.0, -7_000.0);
+        assert!(
+            far.cmple(Vec2::ZERO).all() && far.cmpgt(-tile_size).all(),
+            "{far}"
+        );
+        // Big enough to cover the view's diagonal wherever it's snapped to
+        let size = world.get::<Sprite>(layers[0]).unwrap().custom_size.unwrap();
+        assert!(
+            size.cmpge(Vec2::splat(1000.0) + tile_size * 2.0).all(),
+            "{size}"
+        );
+    }
+
+    #[test]
+    fn music_bus_sets_the_playing_music_volume() {
+        use std::sync::Mutex;
+
+        // Stands in for AudioSink, which needs an audio device
+        #[derive(Component, Default)]
+        struct TestSink(Mutex<f32>);
+        impl AudioSinkPlayback for TestSink {
+            fn volume(&self) -> f32 {
+                *self.0.lock().unwrap()
+            }
+            fn set_volume(&self, volume: f32) {
+                *self.0.lock().unwrap() = volume;
+            }
+            fn speed(&self) -> f32 {
+                1.0
+            }
+            fn set_speed(&self, _: f32) {}
+            fn play(&self) {}
+            fn pause(&self) {}
+            fn is_paused(&self) -> bool {
+                false
+            }
+            fn stop(&self) {}
+            fn empty(&self) -> bool {
+                false
+            }
+        }
+
+        let mut world = World::new();
+        world.insert_resource(AudioVolumes {
+            master: 0.8,
+            music: 1.0,
+            sfx: 1.0,
+            ui: 0.5,
+        });
+        world.insert_resource(GameAssets {
+            spritesheet: Handle::default(),
+            player_animations: Handle::default(),
+            rock: Handle::default(),
+            music: Handle::weak_from_u128(9),
+        });
+        world.run_system_once(start_music);
+        let music = world
+            .query_filtered::<Entity, With<Handle<AudioSource>>>()
+            .single(&world);
+        let settings = world.get::<PlaybackSettings>(music).unwrap();
+        assert!(matches!(settings.mode, bevy::audio::PlaybackMode::Loop));
+        assert_eq!(settings.volume.get(), MUSIC_VOLUME * 0.8);
+        world.entity_mut(music).insert(TestSink::default());
+        let ui = world
+            .spawn((
+                BusVolume {
+                    bus: AudioBus::Ui,
+                    volume: 1.0,
+                },
+                TestSink::default(),
+            ))
+            .id();
+
+        let mut apply = IntoSystem::into_system(apply_bus_volumes::<TestSink>);
+        apply.initialize(&mut world);
+        let mut volumes = |world: &mut World| {
+            apply.run((), world);
+            [music, ui].map(|sound| world.get::<TestSink>(sound).unwrap().volume())
+        };
+        // New sinks pick up the bus straight away
+        assert_eq!(volumes(&mut world), [MUSIC_VOLUME * 0.8, 0.4]);
+
+        world.resource_mut::<AudioVolumes>().music = 0.0;
+        assert_eq!(volumes(&mut world), [0.0, 0.4]);
+        world.resource_mut::<AudioVolumes>().music = 0.5;
+        assert_eq!(volumes(&mut world), [MUSIC_VOLUME * 0.4, 0.4]);
+    }
+
+    #[test]
+    fn occluders_fade_while_the_player_is_behind_them() {
+        use bevy::render::primitives::Aabb;
+
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        world.insert_resource(OcclusionFadeSettings {
+            faded_alpha: 0.35,
+            rate: 8.0,
+        });
+        let mut materials = Assets::<ColorMaterial>::default();
+        let material = materials.add(Color::WHITE);
+        world.insert_resource(materials);
+        let bounds = Aabb::from_min_max(Vec3::splat(-20.0), Vec3::splat(20.0));
+        let occluder = |x: f32, z: f32| {
+            (
+                FadeWhenOccluding,
+                bounds,
+                GlobalTransform::from_xyz(x, 0.0, z),
+            )
+        };
+        // Two overlapping trees, a drawn-over wall further off, and a rug under the player
+        let trees = [
+            world.spawn((occluder(0.0, 2.0), Sprite::default())).id(),
+            world.spawn((occluder(10.0, 3.0), Sprite::default())).id(),
+        ];
+        world.spawn((occluder(100.0, 2.0), material.clone()));
+        let rug = world.spawn((occluder(0.0, -1.0), Sprite::default())).id();
+        let player = world
+            .spawn((PlayerTag, GlobalTransform::from_xyz(5.0, 0.0, 1.0)))
+            .id();
+        let run_for = |world: &mut World, secs: f32| {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_secs_f32(secs));
+            world.run_system_once(fade_occluders);
+            let alpha = |entity| world.get::<Sprite>(entity).unwrap().color.alpha();
+            let wall = world
+                .resource::<Assets<ColorMaterial>>()
+                .get(&material)
+                .unwrap()
+                .color
+                .alpha();
+            [alpha(trees[0]), alpha(trees[1]), wall, alpha(rug)]
+        };
+
+        // Both trees ease down together, the wall and rug stay solid
+        let [a, b, wall, rug_alpha] = run_for(&mut world, 0.05);
+        assert!(a < 1.0 && a > 0.35 && a == b, "{a} {b}");
+        assert_eq!((wall, rug_alpha), (1.0, 1.0));
+        assert_eq!(run_for(&mut world, 2.0), [0.35, 0.35, 1.0, 1.0]);
+
+        // Behind the wall instead
+        *world.get_mut::<GlobalTransform>(player).unwrap() =
+            GlobalTransform::from_xyz(95.0, 0.0, 1.0);
+        let [a, _, wall, _] = run_for(&mut world, 0.05);
+        assert!(a > 0.35 && wall < 1.0, "{a} {wall}");
+        assert_eq!(run_for(&mut world, 2.0), [1.0, 1.0, 0.35, 1.0]);
+    }
+
+    #[test]
+    fn benchmark_runs_its_frames_and_reports() {
+        let mut app = physics_app();
+        app.insert_resource(Benchmark::parse(Some(
+            "frames=20,enemies=4,projectiles=6,particles=10",
+        )))
+        .insert_resource(MoveQuantization {
+            enabled: false,
+            step: 1.0,
+        })
+        .add_event::<TweenCompleted>()
+        .add_systems(Startup, spawn_benchmark_load)
+        .add_systems(
+            Update,
+            (
+                apply_kinematics,
+                animate_rustles,
+                run_tweens,
+                turn_benchmark_enemies,
+            ),
+        )
+        .add_systems(Last, run_benchmark);
+
+        let mut updates = 0;
+        while app.should_exit().is_none() {
+            assert!(updates < 100, "benchmark never quit");
+            app.update();
+            updates += 1;
+        }
+        // One frame for the spawning, then the timed ones
+        assert_eq!(updates, 21);
+        assert_eq!(app.should_exit(), Some(AppExit::Success));
+
+        let world = app.world_mut();
+        let enemies = world
+            .query_filtered::<(), With<BenchmarkEnemy>>()
+            .iter(world)
+            .count();
+        let projectiles = world
+            .query_filtered::<(), With<Sensor>>()
+            .iter(world)
+            .count();
+        let leaves = world.query::<&Rustle>().iter(world).count();
+        assert_eq!((enemies, projectiles, leaves), (4, 6, 10));
+
+        let report = world.resource::<Benchmark>().report.unwrap();
+        assert_eq!(report.frames, 20);
+        assert!(report.entities >= 20);
+        assert!(report.average_ms > 0.0);
+        assert!(report.p95_ms <= report.p99_ms && report.p99_ms <= report.worst_ms);
+    }
 }